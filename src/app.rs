@@ -1,5 +1,7 @@
 //! clap App for command cli
 use clap::{Command, Arg, ArgAction};
+use dbang::audit_log::AuditEvent;
+use dbang::trust::TrustLevel;
 
 pub const VERSION: &str = "0.2.2";
 
@@ -65,21 +67,42 @@ pub fn build_app() -> Command {
     let trust_command = Command::new("trust")
         .about("Trust management for catalogs")
         .subcommand(Command::new("list")
-            .about("List trusted catalogs")
+            .about("List trust rules, and rules matched by local catalogs")
+            .arg(Arg::new("repo_name")
+                .required(false)
+                .help("Show the rule matched by the catalog")
+                .index(1)
+            )
         )
         .subcommand(Command::new("add")
-            .about("Add new trusted catalog")
+            .about("Add trust rule for catalogs")
             .arg(Arg::new("repo_name")
                 .required(true)
-                .help("GitHub repo name, e.g. github_user or github_user/repo")
+                .help("GitHub repo name or pattern, e.g. github_user, github_user/repo, my-org/*, */dbang-catalog or github.com/my-org/*")
                 .index(1)
             )
+            .arg(
+                Arg::new("level")
+                    .long("level")
+                    .num_args(1)
+                    .value_parser(TrustLevel::NAMES)
+                    .default_value("auto-accept")
+                    .help("Trust level, and never to deny the catalogs")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("key")
+                    .long("key")
+                    .num_args(1)
+                    .help("Public key of catalog author, in base64 or a minisign public key file. Catalog without valid signature will be refused")
+                    .required(false)
+            )
         )
         .subcommand(Command::new("delete")
-            .about("Delete trusted catalog")
+            .about("Delete trust rule")
             .arg(Arg::new("repo_name")
                 .required(true)
-                .help("GitHub repo name, e.g. github_user or github_user/repo")
+                .help("GitHub repo name or pattern of the rule")
                 .index(1)
             )
         );
@@ -92,6 +115,35 @@ pub fn build_app() -> Command {
                 .help("Custom app name for script")
                 .required(false),
         )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Replace existing app or file with the same name")
+                .required(false),
+        )
+        .arg(
+            Arg::new("bin_dir")
+                .long("bin-dir")
+                .num_args(1)
+                .help("Bin dir for app, default is `bin-dir` in config or ~/.dbang/bin")
+                .required(false),
+        )
+        .arg(
+            Arg::new("compile")
+                .long("compile")
+                .action(ArgAction::SetTrue)
+                .help("Compile script to a standalone executable by `deno compile`")
+                .required(false),
+        )
+        .arg(
+            Arg::new("target")
+                .long("target")
+                .num_args(1)
+                .requires("compile")
+                .help("Target of `deno compile`, e.g. x86_64-unknown-linux-gnu, and executable saved in ~/.dbang/dist/<target>")
+                .required(false),
+        )
         .arg(
             Arg::new("script")
                 .help("script full name")
@@ -107,7 +159,22 @@ pub fn build_app() -> Command {
                 .index(1)
         );
     let apps_command = Command::new("apps")
-        .about("List installed apps");
+        .about("List installed apps")
+        .subcommand(Command::new("upgrade")
+            .about("Upgrade installed apps from their catalogs")
+            .arg(Arg::new("name")
+                .required(false)
+                .help("App name")
+                .index(1)
+            )
+            .arg(
+                Arg::new("all")
+                    .long("all")
+                    .action(ArgAction::SetTrue)
+                    .help("Upgrade all installed apps")
+                    .required(false)
+            )
+        );
     let catalog_command = Command::new("catalog")
         .about("Catalog management")
         .subcommand(Command::new("list")
@@ -120,6 +187,33 @@ pub fn build_app() -> Command {
                 .help("GitHub repo name, e.g. github_user or github_user/repo")
                 .index(1)
             )
+            .arg(Arg::new("script")
+                .required(false)
+                .help("Script name, display the script only")
+                .index(2)
+            )
+            .arg(
+                Arg::new("effective")
+                    .long("effective")
+                    .action(ArgAction::SetTrue)
+                    .help("Display scripts with catalog defaults applied")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("resolved")
+                    .long("resolved")
+                    .action(ArgAction::SetTrue)
+                    .help("Display scripts with the catalogs they come from, including scripts of included catalogs")
+                    .required(false)
+            )
+        )
+        .subcommand(Command::new("search")
+            .about("Search scripts in local catalogs")
+            .arg(Arg::new("keyword")
+                .required(true)
+                .help("Keyword in script name or description")
+                .index(1)
+            )
         )
         .subcommand(Command::new("add")
             .about("Add new catalog")
@@ -144,6 +238,275 @@ pub fn build_app() -> Command {
                 .help("GitHub repo name, e.g. github_user or github_user/repo")
                 .index(1)
             )
+        )
+        .subcommand(Command::new("relock")
+            .about("Cache scripts of local catalog again, and refresh their Deno lockfiles")
+            .arg(Arg::new("repo_name")
+                .required(true)
+                .help("GitHub repo name, e.g. github_user or github_user/repo")
+                .index(1)
+            )
+        )
+        .subcommand(Command::new("lint")
+            .about("Check dbang-catalog.json for problems")
+            .arg(Arg::new("target")
+                .required(false)
+                .help("dbang-catalog.json path, directory or local catalog repo name, default is dbang-catalog.json of current directory")
+                .index(1)
+            )
+            .arg(
+                Arg::new("check_urls")
+                    .long("check-urls")
+                    .action(ArgAction::SetTrue)
+                    .help("Check script and import map URLs are reachable")
+                    .required(false)
+            )
+        )
+        .subcommand(Command::new("sign")
+            .about("Sign dbang-catalog.json with Ed25519 key, and write dbang-catalog.json.minisig next to it")
+            .arg(Arg::new("catalog")
+                .required(false)
+                .help("dbang-catalog.json path, default is dbang-catalog.json of current directory")
+                .index(1)
+            )
+            .arg(
+                Arg::new("key")
+                    .long("key")
+                    .num_args(1)
+                    .help("Secret key file, default is ~/.dbang/keys/dbang.key")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("repo")
+                    .long("repo")
+                    .num_args(1)
+                    .help("GitHub repo of the catalog, e.g. linux-china, required unless the catalog is a local catalog repo")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("path")
+                    .long("path")
+                    .num_args(1)
+                    .help("Path of the catalog file in the repo, default is its file name")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("generate_key")
+                    .long("generate-key")
+                    .action(ArgAction::SetTrue)
+                    .help("Generate a new key pair if the secret key does not exist")
+                    .required(false)
+            )
+        )
+        .subcommand(Command::new("add-script")
+            .about("Add script to dbang-catalog.json")
+            .arg(Arg::new("name")
+                .required(true)
+                .help("Script name")
+                .index(1)
+            )
+            .arg(Arg::new("script_ref")
+                .required(true)
+                .help("Script reference, a local file, a URL or a npm package")
+                .index(2)
+            )
+            .arg(
+                Arg::new("description")
+                    .long("description")
+                    .num_args(1)
+                    .help("Script description")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("permissions")
+                    .long("permissions")
+                    .num_args(1..)
+                    .value_delimiter(',')
+                    .help("Permissions for the script, e.g. allow-net,allow-read")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("args")
+                    .long("args")
+                    .num_args(1)
+                    .action(ArgAction::Append)
+                    .allow_hyphen_values(true)
+                    .help("Default argument for the script, repeat for multiple arguments")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("deno")
+                    .long("deno")
+                    .num_args(1)
+                    .help("Deno version for the script")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("catalog")
+                    .long("catalog")
+                    .num_args(1)
+                    .help("dbang-catalog.json path, default is dbang-catalog.json of current directory")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("force")
+                    .long("force")
+                    .action(ArgAction::SetTrue)
+                    .help("Replace the existing script")
+                    .required(false)
+            )
+        );
+    let config_command = Command::new("config")
+        .about("DBang config management")
+        .subcommand(Command::new("list")
+            .about("List config")
+        )
+        .subcommand(Command::new("set")
+            .about("Set config value")
+            .arg(Arg::new("key")
+                .required(true)
+                .help("Config key, e.g. bin-dir")
+                .index(1)
+            )
+            .arg(Arg::new("value")
+                .required(true)
+                .help("Config value")
+                .index(2)
+            )
+        )
+        .subcommand(Command::new("unset")
+            .about("Remove config value")
+            .arg(Arg::new("key")
+                .required(true)
+                .help("Config key, e.g. bin-dir")
+                .index(1)
+            )
+        );
+    let setup_command = Command::new("setup")
+        .about("Add bin dirs of apps to PATH for bash, zsh or fish")
+        .arg(
+            Arg::new("shell")
+                .long("shell")
+                .num_args(1)
+                .value_parser(["bash", "zsh", "fish"])
+                .help("Shell name, default is detected from $SHELL")
+                .required(false),
+        )
+        .arg(
+            Arg::new("append")
+                .long("append")
+                .action(ArgAction::SetTrue)
+                .help("Append PATH lines to rc file of shell")
+                .required(false),
+        );
+    let doctor_command = Command::new("doctor")
+        .about("Check DBang environment, such as PATH, Deno, dbang-shim, apps and catalogs")
+        .arg(
+            Arg::new("fix")
+                .long("fix")
+                .action(ArgAction::SetTrue)
+                .help("Repair problems that can be fixed automatically")
+                .required(false),
+        );
+    let cache_command = Command::new("cache")
+        .about("Deno cache management, and every catalog has its own Deno cache")
+        .subcommand(Command::new("list")
+            .about("List Deno caches of local catalogs")
+        )
+        .subcommand(Command::new("size")
+            .about("Display size of Deno caches")
+            .arg(Arg::new("repo_name")
+                .required(false)
+                .help("GitHub repo name, e.g. github_user or github_user/repo, default is all local catalogs")
+                .index(1)
+            )
+        )
+        .subcommand(Command::new("clean")
+            .about("Remove Deno caches, and lockfiles of scripts are kept")
+            .arg(Arg::new("repo_name")
+                .required(false)
+                .help("GitHub repo name, e.g. github_user or github_user/repo, default is all local catalogs")
+                .index(1)
+            )
+        )
+        .subcommand(Command::new("warm")
+            .about("Cache scripts changed since they were cached last time")
+            .arg(Arg::new("target")
+                .required(true)
+                .help("Script or catalog, e.g. hello@linux-china or linux-china")
+                .index(1)
+            )
+        );
+    let log_command = Command::new("log")
+        .about("Show audit log of catalog approvals, trust changes, installs and script runs")
+        .arg(
+            Arg::new("event")
+                .long("event")
+                .num_args(1)
+                .value_parser(AuditEvent::NAMES)
+                .help("Show events of the kind only")
+                .required(false),
+        )
+        .arg(
+            Arg::new("catalog")
+                .long("catalog")
+                .num_args(1)
+                .help("Show events of catalog only, e.g. github_user or github_user/repo")
+                .required(false),
+        )
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .num_args(1)
+                .help("Show events of script or app only")
+                .required(false),
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .num_args(1)
+                .help("Show events since a date, e.g. 2024-01-31, or a duration ago, e.g. 12h or 7d")
+                .required(false),
+        )
+        .arg(
+            Arg::new("limit")
+                .long("limit")
+                .num_args(1)
+                .value_parser(clap::value_parser!(usize))
+                .help("Show the latest events only")
+                .required(false),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output events in JSON lines")
+                .required(false),
+        );
+    let self_update_command = Command::new("self-update")
+        .about("Update dbang and dbang-shim to the latest release")
+        .arg(
+            Arg::new("rollback")
+                .long("rollback")
+                .action(ArgAction::SetTrue)
+                .help("Restore dbang and dbang-shim replaced by last update")
+                .required(false),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("rollback")
+                .help("Reinstall the latest release even if it's not newer")
+                .required(false),
+        );
+    let init_command = Command::new("init")
+        .about("Create a new catalog repository with dbang-catalog.json")
+        .arg(
+            Arg::new("dir")
+                .help("Catalog directory, default is current directory")
+                .required(false)
+                .index(1)
         );
     let complete_command = Command::new("complete")
         .about("Generate shell completion for zsh & bash")
@@ -188,6 +551,14 @@ pub fn build_app() -> Command {
                 .help("Suppress diagnostic output")
                 .required(false),
         )
+        .arg(
+            Arg::new("offline")
+                .long("offline")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("No network access, use cached catalogs and scripts only, same as DBANG_OFFLINE=1")
+                .required(false),
+        )
         .subcommand(run_command)
         .subcommand(open_command)
         .subcommand(deno_command)
@@ -196,6 +567,13 @@ pub fn build_app() -> Command {
         .subcommand(uninstall_command)
         .subcommand(apps_command)
         .subcommand(catalog_command)
+        .subcommand(cache_command)
+        .subcommand(init_command)
+        .subcommand(config_command)
+        .subcommand(setup_command)
+        .subcommand(doctor_command)
+        .subcommand(log_command)
+        .subcommand(self_update_command)
         .subcommand(complete_command)
         .arg(Arg::new("script")
             .required(false)
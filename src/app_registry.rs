@@ -0,0 +1,247 @@
+//! Registry of installed apps, saved in ~/.dbang/apps.json
+use std::path::{Path, PathBuf};
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::catalog::{Artifact, Catalog};
+use crate::dbang_utils;
+
+/// version of apps.json format
+pub const APPS_FILE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppRegistry {
+    pub version: u32,
+    /// apps in install order, key is app name
+    pub apps: IndexMap<String, AppEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct AppEntry {
+    /// script full name, e.g. `hello@linux-china`
+    pub script: String,
+    /// catalog repo full name, e.g. `linux-china/dbang-catalog`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub catalog: Option<String>,
+    /// git ref of catalog, a commit id if resolved, otherwise `HEAD`
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    #[serde(rename = "installed-at")]
+    pub installed_at: String,
+    #[serde(rename = "upgraded-at", skip_serializing_if = "Option::is_none")]
+    pub upgraded_at: Option<String>,
+    /// resolved Deno version on install or upgrade
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deno: Option<String>,
+    /// Deno permissions granted to the app
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// standalone executable by `deno compile`, otherwise a link to dbang-shim
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compiled: bool,
+    /// target of `deno compile --target`, None for current platform
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// bin dir of app link or executable, None for ~/.dbang/bin
+    #[serde(rename = "bin-dir", skip_serializing_if = "Option::is_none")]
+    pub bin_dir: Option<String>,
+}
+
+impl AppEntry {
+    pub fn new(script_full_name: &str) -> AppEntry {
+        let catalog = script_full_name.split_once('@')
+            .map(|(_, repo_name)| Catalog::get_full_repo_name(repo_name));
+        AppEntry {
+            script: script_full_name.to_string(),
+            catalog,
+            git_ref: "HEAD".to_string(),
+            installed_at: chrono::Local::now().to_rfc3339(),
+            upgraded_at: None,
+            deno: None,
+            permissions: vec![],
+            compiled: false,
+            target: None,
+            bin_dir: None,
+        }
+    }
+
+    pub fn get_bin_dir(&self) -> anyhow::Result<PathBuf> {
+        match &self.bin_dir {
+            Some(bin_dir) => dbang_utils::expand_home(bin_dir),
+            None => dbang_utils::dbang_bin_dir(),
+        }
+    }
+
+    /// path of app executable, and executables cross compiled for other targets are in ~/.dbang/dist/<target>
+    pub fn executable_path(&self, app_name: &str) -> anyhow::Result<PathBuf> {
+        match &self.target {
+            Some(target) => {
+                let file_name = if target.contains("windows") { format!("{}.exe", app_name) } else { app_name.to_string() };
+                Ok(dbang_utils::dbang_dir()?.join("dist").join(target).join(file_name))
+            }
+            None => {
+                let file_name = if self.compiled && cfg!(target_os = "windows") { format!("{}.exe", app_name) } else { app_name.to_string() };
+                Ok(self.get_bin_dir()?.join(file_name))
+            }
+        }
+    }
+
+    /// script name and repo name of `script@repo`
+    pub fn script_and_repo(&self) -> Option<(&str, &str)> {
+        self.script.split_once('@')
+    }
+
+    /// record resolved Deno version and permissions of the artifact
+    pub fn resolve_with(&mut self, artifact: &Artifact) {
+        self.deno = artifact.get_deno_version();
+        self.permissions = artifact.get_deno_permissions();
+    }
+}
+
+/// names reserved by Windows for devices
+const WINDOWS_RESERVED_NAMES: &[&str] = &["con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6",
+    "com7", "com8", "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9"];
+
+/// validate app name is safe as a file name on all platforms and not reserved by dbang
+pub fn validate_app_name(app_name: &str) -> Result<(), String> {
+    if app_name == "dbang" || app_name.starts_with("dbang-") || app_name == "deno" {
+        return Err("dbang, deno and dbang-* are reserved names, please use other names".to_string());
+    }
+    let name_pattern = Regex::new(r"^[A-Za-z0-9][A-Za-z0-9._-]{0,63}$").unwrap();
+    if !name_pattern.is_match(app_name) {
+        return Err(format!("Invalid app name `{}`: only letters, digits, `.`, `_` and `-` are allowed, \
+            start with a letter or digit, and max length is 64", app_name));
+    }
+    let stem = app_name.split('.').next().unwrap_or(app_name).to_lowercase();
+    if WINDOWS_RESERVED_NAMES.contains(&stem.as_str()) {
+        return Err(format!("Invalid app name `{}`: reserved device name on Windows", app_name));
+    }
+    Ok(())
+}
+
+/// executables on PATH with same name as app, except the app itself
+pub fn find_path_collisions(app_name: &str, executable_path: &Path) -> Vec<PathBuf> {
+    let app_dir = executable_path.parent().and_then(|dir| dir.canonicalize().ok());
+    which::which_all(app_name)
+        .map(|paths| paths.filter(|path| path.parent().and_then(|dir| dir.canonicalize().ok()) != app_dir).collect())
+        .unwrap_or_default()
+}
+
+/// reason to refuse installing the app over an existing file, None if it's safe to install
+pub fn check_overwrite(app_name: &str, app_entry: &AppEntry) -> anyhow::Result<Option<String>> {
+    let executable_path = app_entry.executable_path(app_name)?;
+    if let Some(installed) = get(app_name)? {
+        if installed.script != app_entry.script {
+            return Ok(Some(format!("{} is installed for {}", app_name, installed.script)));
+        }
+    } else if executable_path.symlink_metadata().is_ok() {
+        return Ok(Some(format!("{} exists and it's not installed by dbang", executable_path.to_string_lossy())));
+    }
+    Ok(None)
+}
+
+fn get_apps_file() -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?.join("apps.json"))
+}
+
+/// aliases.json of dbang 0.2, a map of app name to `script@repo`
+fn get_legacy_aliases_file() -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?.join("aliases.json"))
+}
+
+pub fn load() -> anyhow::Result<AppRegistry> {
+    let apps_file = get_apps_file()?;
+    if apps_file.exists() {
+        let data = std::fs::read_to_string(&apps_file)?;
+        let registry: AppRegistry = serde_json::from_str(&data)?;
+        return Ok(registry);
+    }
+    let mut registry = AppRegistry { version: APPS_FILE_VERSION, apps: IndexMap::new() };
+    let aliases_file = get_legacy_aliases_file()?;
+    if aliases_file.exists() {
+        registry = migrate_aliases(&std::fs::read_to_string(&aliases_file)?)?;
+        save(&registry)?;
+        std::fs::rename(&aliases_file, aliases_file.with_extension("json.bak"))?;
+    }
+    Ok(registry)
+}
+
+fn migrate_aliases(aliases_json: &str) -> anyhow::Result<AppRegistry> {
+    let aliases: IndexMap<String, String> = serde_json::from_str(aliases_json)?;
+    let mut apps = IndexMap::new();
+    for (app_name, script_full_name) in aliases {
+        let mut entry = AppEntry::new(&script_full_name);
+        if let Some((script_name, repo_name)) = script_full_name.split_once('@') {
+            if let Ok(artifact) = Artifact::read_from_local(repo_name, script_name) {
+                entry.resolve_with(&artifact);
+            }
+        }
+        apps.insert(app_name, entry);
+    }
+    Ok(AppRegistry { version: APPS_FILE_VERSION, apps })
+}
+
+pub fn save(registry: &AppRegistry) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dbang_utils::dbang_dir()?)?;
+    let data = dbang_utils::to_pretty_json(registry, "  ")?;
+    std::fs::write(get_apps_file()?, data)?;
+    Ok(())
+}
+
+pub fn all() -> anyhow::Result<IndexMap<String, AppEntry>> {
+    Ok(load()?.apps)
+}
+
+pub fn get(app_name: &str) -> anyhow::Result<Option<AppEntry>> {
+    Ok(load()?.apps.get(app_name).cloned())
+}
+
+/// add or replace app
+pub fn add(app_name: &str, entry: AppEntry) -> anyhow::Result<()> {
+    let mut registry = load()?;
+    registry.apps.insert(app_name.to_string(), entry);
+    save(&registry)
+}
+
+pub fn remove(app_name: &str) -> anyhow::Result<()> {
+    let mut registry = load()?;
+    registry.apps.shift_remove(app_name);
+    save(&registry)
+}
+
+pub fn remove_by_repo_name(repo_name: &str) -> anyhow::Result<()> {
+    let catalog_full_name = Catalog::get_full_repo_name(repo_name);
+    let mut registry = load()?;
+    registry.apps.retain(|_, entry| entry.catalog.as_deref() != Some(catalog_full_name.as_str()));
+    save(&registry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_app_name() {
+        assert!(validate_app_name("hello").is_ok());
+        assert!(validate_app_name("http-server.ts").is_ok());
+        assert!(validate_app_name("deno").is_err());
+        assert!(validate_app_name("dbang-shim").is_err());
+        assert!(validate_app_name("../hello").is_err());
+        assert!(validate_app_name("a/b").is_err());
+        assert!(validate_app_name("-rf").is_err());
+        assert!(validate_app_name("hello world").is_err());
+        assert!(validate_app_name("con.txt").is_err());
+        assert!(validate_app_name("").is_err());
+    }
+
+    #[test]
+    fn test_migrate_aliases() {
+        let registry = migrate_aliases(r#"{"hello": "hello@linux-china", "serve": "http-server@linux-china/demo"}"#).unwrap();
+        assert_eq!(registry.version, APPS_FILE_VERSION);
+        let serve = registry.apps.get("serve").unwrap();
+        assert_eq!(serve.catalog.as_deref(), Some("linux-china/demo"));
+        assert_eq!(serve.script_and_repo(), Some(("http-server", "linux-china/demo")));
+        assert_eq!(registry.apps.get("hello").unwrap().catalog.as_deref(), Some("linux-china/dbang-catalog"));
+    }
+}
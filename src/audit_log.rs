@@ -0,0 +1,236 @@
+//! Append-only audit log of catalog approvals, trust changes, installs and script runs in ~/.dbang/audit.jsonl
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::dbang_utils;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditEvent {
+    CatalogAccept,
+    CatalogReject,
+    TrustAdd,
+    TrustDelete,
+    Install,
+    Uninstall,
+    Run,
+}
+
+impl AuditEvent {
+    pub const NAMES: [&'static str; 7] = ["catalog-accept", "catalog-reject", "trust-add", "trust-delete", "install", "uninstall", "run"];
+
+    pub fn from_name(name: &str) -> Option<AuditEvent> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = serde_json::to_value(self).ok().and_then(|value| value.as_str().map(|x| x.to_string())).unwrap_or_default();
+        write!(f, "{}", name)
+    }
+}
+
+/// one line in audit log, and fields not for the event are omitted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuditEntry {
+    /// RFC 3339 time of the event
+    pub time: String,
+    pub event: AuditEvent,
+    /// catalog repo full name, e.g. `linux-china/dbang-catalog`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub catalog: Option<String>,
+    /// script or app name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// SHA-256 of script content resolved by Deno
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deno: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// other info of the event, such as trust rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(event: AuditEvent) -> AuditEntry {
+        AuditEntry {
+            time: chrono::Local::now().to_rfc3339(),
+            event,
+            catalog: None,
+            script: None,
+            url: None,
+            sha256: None,
+            permissions: vec![],
+            deno: None,
+            exit_code: None,
+            duration_ms: None,
+            detail: None,
+        }
+    }
+
+    pub fn catalog(mut self, catalog_full_name: &str) -> AuditEntry {
+        self.catalog = Some(catalog_full_name.to_string());
+        self
+    }
+
+    pub fn script(mut self, script_name: &str) -> AuditEntry {
+        self.script = Some(script_name.to_string());
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> AuditEntry {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// save the entry, and failure is reported as warning without breaking the command
+    pub fn record(self) {
+        if let Err(e) = append(&self) {
+            eprintln!("{}", format!("Warning: failed to write audit log: {}", e).yellow());
+        }
+    }
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = chrono::DateTime::parse_from_rfc3339(&self.time)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or(self.time.clone());
+        write!(f, "{}  {:<14}", time, self.event.to_string())?;
+        let subject = match (&self.script, &self.catalog) {
+            (Some(script), Some(catalog)) => format!("{}@{}", script, catalog),
+            (Some(script), None) => script.clone(),
+            (None, Some(catalog)) => catalog.clone(),
+            (None, None) => String::new(),
+        };
+        write!(f, "  {}", subject)?;
+        if let Some(exit_code) = self.exit_code {
+            write!(f, "  exit {}", exit_code)?;
+        }
+        if let Some(duration_ms) = self.duration_ms {
+            write!(f, "  {:.1}s", duration_ms as f64 / 1000.0)?;
+        }
+        if let Some(deno) = &self.deno {
+            write!(f, "  deno {}", deno)?;
+        }
+        if !self.permissions.is_empty() {
+            write!(f, "  {}", self.permissions.join(" "))?;
+        }
+        if let Some(detail) = &self.detail {
+            write!(f, "  ({})", detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// filters of `dbang log`
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub event: Option<AuditEvent>,
+    /// catalog name or user, e.g. `linux-china` or `linux-china/dbang-catalog`
+    pub catalog: Option<String>,
+    pub script: Option<String>,
+    pub since: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        if self.event.is_some_and(|event| event != entry.event) {
+            return false;
+        }
+        if let Some(catalog) = &self.catalog {
+            let entry_catalog = entry.catalog.as_deref().unwrap_or_default();
+            if entry_catalog != catalog && !entry_catalog.starts_with(&format!("{}/", catalog)) {
+                return false;
+            }
+        }
+        if self.script.is_some() && entry.script != self.script {
+            return false;
+        }
+        if let Some(since) = self.since {
+            match chrono::DateTime::parse_from_rfc3339(&entry.time) {
+                Ok(time) if time >= since => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+pub fn get_audit_log_file() -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?.join("audit.jsonl"))
+}
+
+fn append(entry: &AuditEntry) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dbang_utils::dbang_dir()?)?;
+    let mut output = OpenOptions::new().create(true).append(true).open(get_audit_log_file()?)?;
+    writeln!(output, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// entries matched by filter in time order, and lines not parsed are skipped
+pub fn read(filter: &AuditFilter) -> anyhow::Result<Vec<AuditEntry>> {
+    let audit_log_file = get_audit_log_file()?;
+    if !audit_log_file.exists() {
+        return Ok(vec![]);
+    }
+    let input = std::fs::File::open(&audit_log_file)?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(input).lines() {
+        if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line?) {
+            if filter.matches(&entry) {
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// SHA-256 of file in hex, None if not readable
+pub fn file_sha256(path: &Path) -> Option<String> {
+    let data = std::fs::read(path).ok()?;
+    Some(format!("{:x}", Sha256::digest(data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_json() {
+        let mut entry = AuditEntry::new(AuditEvent::Run).catalog("linux-china/dbang-catalog").script("hello");
+        entry.exit_code = Some(0);
+        let json_text = serde_json::to_string(&entry).unwrap();
+        assert!(json_text.contains(r#""event":"run""#));
+        assert!(json_text.contains(r#""exit-code":0"#));
+        assert!(!json_text.contains("permissions"));
+        assert_eq!(serde_json::from_str::<AuditEntry>(&json_text).unwrap(), entry);
+        assert_eq!(AuditEvent::CatalogAccept.to_string(), "catalog-accept");
+    }
+
+    #[test]
+    fn test_filter() {
+        let entry = AuditEntry::new(AuditEvent::Run).catalog("linux-china/dbang-catalog").script("hello");
+        assert!(AuditFilter::default().matches(&entry));
+        assert!(AuditFilter { catalog: Some("linux-china".to_string()), ..Default::default() }.matches(&entry));
+        assert!(!AuditFilter { catalog: Some("linux".to_string()), ..Default::default() }.matches(&entry));
+        assert!(!AuditFilter { event: Some(AuditEvent::Install), ..Default::default() }.matches(&entry));
+        let since = chrono::Local::now().fixed_offset() + chrono::Duration::hours(1);
+        assert!(!AuditFilter { since: Some(since), ..Default::default() }.matches(&entry));
+    }
+}
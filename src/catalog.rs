@@ -1,16 +1,90 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use indexmap::IndexMap;
 use reqwest::blocking::Client;
 use std::{fs};
-use std::path::Path;
-use crate::{dbang_utils, deno_cli, deno_versions};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use anyhow::bail;
+use colored::*;
+use crate::{catalog_include, catalog_sign, dbang_utils, deno_cache, deno_cli, deno_compat, deno_versions, trust};
+use crate::errors::{self, DbangError};
+
+/// max count of scripts cached concurrently by `deno cache`
+pub const CACHE_JOBS: usize = 4;
+/// JSON Schema of dbang-catalog.json, source is schema/dbang-catalog.schema.json
+pub const SCHEMA_URL: &str = "https://dbang.dev/schema/dbang-catalog.schema.json";
 
 #[derive(Serialize, Deserialize, Debug)]
+#[non_exhaustive]
 pub struct Catalog {
-    pub scripts: HashMap<String, Artifact>,
+    /// JSON Schema of dbang-catalog.json
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// cache scripts on first run, instead of caching all scripts when the catalog is added or updated
+    #[serde(rename = "lazy-cache", skip_serializing_if = "Option::is_none")]
+    pub lazy_cache: Option<bool>,
+    /// options inherited by every script, and scripts can override them field by field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<Defaults>,
+    /// other catalogs whose scripts are added to this catalog, resolved when the catalog is loaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub includes: Option<Vec<Include>>,
+    /// scripts in the author's order, followed by scripts of included catalogs
+    pub scripts: IndexMap<String, Artifact>,
+    /// source catalogs of included scripts, recorded in local catalogs after includes resolved
+    #[serde(rename = "included-scripts", default, skip_serializing_if = "IndexMap::is_empty")]
+    pub included_scripts: IndexMap<String, String>,
+    /// GitHub repos and URLs of included catalogs, which are checked by trust rules with the catalog
+    #[serde(skip)]
+    pub included_catalogs: Vec<String>,
+}
+
+/// included catalog, format as `"my-org/tools"` or `{"catalog": "my-org/tools", "prefix": "tools-"}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Include {
+    Catalog(String),
+    Prefixed {
+        catalog: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        prefix: Option<String>,
+    },
+}
+
+impl Include {
+    /// GitHub repo, URL, or path relative to the including catalog
+    pub fn catalog(&self) -> &str {
+        match self {
+            Include::Catalog(catalog) | Include::Prefixed { catalog, .. } => catalog,
+        }
+    }
+
+    /// prefix of included script names, empty if not set
+    pub fn prefix(&self) -> &str {
+        match self {
+            Include::Catalog(_) => "",
+            Include::Prefixed { prefix, .. } => prefix.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+/// catalog-level defaults of script options
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Defaults {
+    #[serde(rename = "import-map", skip_serializing_if = "Option::is_none")]
+    pub import_map: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deno: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unstable: Option<Unstable>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[non_exhaustive]
 pub struct Artifact {
     #[serde(rename(serialize = "script-ref", deserialize = "script-ref"))]
     pub script_ref: String,
@@ -27,23 +101,98 @@ pub struct Artifact {
     /// platform os and arch, format as `os-arch`
     #[serde(skip_serializing_if = "Option::is_none")]
     pub platforms: Option<Vec<String>>,
+    /// `true` for blanket `--unstable`, or feature names for `--unstable-<feature>` flags
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub unstable: Option<bool>,
+    pub unstable: Option<Unstable>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub permissions: Option<Vec<String>>,
+    /// `true` to add permissions to the catalog defaults, instead of replacing them
+    #[serde(rename = "merge-permissions", skip_serializing_if = "Option::is_none")]
+    pub merge_permissions: Option<bool>,
     #[serde(rename(serialize = "v8_flags", deserialize = "v8_flags"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub v8_flags: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub args: Option<Vec<String>>,
+    /// SHA-256 of script content in hex, checked after the script is cached, and signed with the catalog
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// unstable setting of script, format as `true` or `["kv", "ffi"]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Unstable {
+    Enabled(bool),
+    Features(Vec<String>),
 }
 
 impl Artifact {
+    #[allow(deprecated)]
+    pub fn new(script_ref: &str) -> Artifact {
+        Artifact {
+            script_ref: script_ref.to_string(),
+            description: None,
+            compat: None,
+            import_map: None,
+            deno: None,
+            platforms: None,
+            unstable: None,
+            permissions: None,
+            merge_permissions: None,
+            v8_flags: None,
+            args: None,
+            sha256: None,
+        }
+    }
+
+    /// effective artifact in local catalog, with defaults of the catalog applied
     pub fn read_from_local(repo_name: &str, artifact_name: &str) -> anyhow::Result<Artifact> {
         let catalog_repo = Catalog::get_full_repo_name(repo_name);
         let catalog = Catalog::read_from_local(&catalog_repo)?;
-        let artifact = catalog.scripts.get(artifact_name).unwrap();
-        Ok(artifact.clone())
+        let artifact = catalog.get_artifact(artifact_name)
+            .ok_or_else(|| DbangError::not_found(format!("{} in catalog {}", artifact_name, catalog_repo),
+                                                 format!("Please check script names by `dbang catalog show {}`", catalog_repo)))?;
+        Ok(artifact)
+    }
+
+    /// effective artifact with defaults for fields not set by the script, and permissions are merged
+    /// with defaults if `merge-permissions` is true
+    pub fn with_defaults(&self, defaults: &Defaults) -> Artifact {
+        let mut artifact = self.clone();
+        artifact.import_map = self.import_map.clone().or(defaults.import_map.clone());
+        artifact.deno = self.deno.clone().or(defaults.deno.clone());
+        artifact.unstable = self.unstable.clone().or(defaults.unstable.clone());
+        artifact.permissions = match (&defaults.permissions, &self.permissions) {
+            (Some(default_permissions), Some(permissions)) if self.merge_permissions == Some(true) => {
+                let mut merged = default_permissions.clone();
+                for permission in permissions {
+                    if !merged.iter().any(|x| to_deno_permission(x) == to_deno_permission(permission)) {
+                        merged.push(permission.clone());
+                    }
+                }
+                Some(merged)
+            }
+            (default_permissions, permissions) => permissions.clone().or(default_permissions.clone()),
+        };
+        artifact.merge_permissions = None;
+        artifact
+    }
+
+    /// changes from this artifact to the other one, format as `key: old -> new`
+    pub fn diff(&self, other: &Artifact) -> Vec<String> {
+        let old_value = serde_json::to_value(self).unwrap_or_default();
+        let new_value = serde_json::to_value(other).unwrap_or_default();
+        let empty = serde_json::Map::new();
+        let old_object = old_value.as_object().unwrap_or(&empty);
+        let new_object = new_value.as_object().unwrap_or(&empty);
+        let mut keys: Vec<&String> = old_object.keys().collect();
+        keys.extend(new_object.keys().filter(|key| !old_object.contains_key(*key)));
+        let to_text = |value: Option<&serde_json::Value>| value.map(|x| x.to_string()).unwrap_or("none".to_string());
+        keys.into_iter()
+            .filter(|key| old_object.get(*key) != new_object.get(*key))
+            .map(|key| format!("{}: {} -> {}", key, to_text(old_object.get(key)), to_text(new_object.get(key))))
+            .collect()
     }
 
     pub fn get_script_http_url(&self, repo_name: &str) -> String {
@@ -53,7 +202,7 @@ impl Artifact {
             self.script_ref.to_string()
         } else {
             let catalog_repo = Catalog::get_full_repo_name(repo_name);
-            format!("https://raw.githubusercontent.com/{}/HEAD/{}", catalog_repo, self.script_ref)
+            format!("{}/{}/HEAD/{}", dbang_utils::github_raw_url(), catalog_repo, self.script_ref)
         };
     }
 
@@ -63,45 +212,116 @@ impl Artifact {
             import_map
         } else {
             let catalog_repo = Catalog::get_full_repo_name(repo_name);
-            format!("https://raw.githubusercontent.com/{}/HEAD/{}", catalog_repo, import_map)
+            format!("{}/{}/HEAD/{}", dbang_utils::github_raw_url(), catalog_repo, import_map)
         };
     }
 
-    pub fn get_deno_config(&self, repo_name: &str) -> String {
+    /// cache script by Deno with `--reload` in Deno cache of the catalog, and write a new lockfile of its dependency graph.
+    /// Fingerprint of the script is recorded to find changed scripts for warming
+    pub fn cache(&self, repo_name: &str, script_name: &str) -> anyhow::Result<()> {
+        self.cache_with_deno(&self.get_deno_bin_path()?, repo_name, script_name, deno_cache::fingerprint(self, repo_name))
+    }
+
+    /// cache script by the Deno binary, which is resolved before caching scripts concurrently
+    fn cache_with_deno(&self, deno_bin_path: &str, repo_name: &str, script_name: &str, fingerprint: Option<String>) -> anyhow::Result<()> {
+        let deno_dir = deno_cache::get_deno_dir(repo_name)?;
+        fs::create_dir_all(&deno_dir)?;
+        let lock_file = get_lock_file(repo_name, script_name)?;
+        if let Some(lock_dir) = lock_file.parent() {
+            fs::create_dir_all(lock_dir)?;
+        }
+        // new lockfile replaces the old one after Deno cached the script successfully
+        let new_lock_file = lock_file.with_extension("json.new");
+        if new_lock_file.exists() {
+            fs::remove_file(&new_lock_file)?;
+        }
+        let mut flags = self.get_unstable_flags();
+        flags.push(format!("--lock={}", new_lock_file.to_string_lossy()));
+        flags.push("--lock-write".to_string());
+        let flags = deno_compat::translate_flags(self.get_deno_version().as_deref(), flags, false);
+        let import_map = self.import_map.as_ref().map(|_| self.get_import_map_http_url(repo_name));
+        let script_url = self.get_script_http_url(repo_name);
+        let output = deno_cli::cache(deno_bin_path, &script_url, &import_map, &flags, &deno_dir)?;
+        if !output.status.success() {
+            if new_lock_file.exists() {
+                fs::remove_file(&new_lock_file)?;
+            }
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().map(|line| line.trim()).find(|line| !line.is_empty()).unwrap_or_default();
+            bail!("`deno cache` exited with {}: {}", output.status.code().unwrap_or(1), reason);
+        }
+        if let Some(expected_sha256) = &self.sha256 {
+            let cached_sha256 = deno_cli::cached_script_sha256(repo_name, deno_bin_path, &script_url);
+            if !cached_sha256.as_deref().is_some_and(|sha256| sha256.eq_ignore_ascii_case(expected_sha256)) {
+                if new_lock_file.exists() {
+                    fs::remove_file(&new_lock_file)?;
+                }
+                let reason = format!("SHA-256 of {} is {}, but {} in catalog", script_name,
+                                     cached_sha256.as_deref().unwrap_or("unknown"), expected_sha256);
+                return Err(DbangError::BadSignature { catalog: Catalog::get_full_repo_name(repo_name), reason }.into());
+            }
+        }
+        if new_lock_file.exists() {
+            fs::rename(&new_lock_file, &lock_file)?;
+        }
+        deno_cache::record_cached(repo_name, script_name, fingerprint)
+    }
+
+    pub fn get_deno_config(&self, repo_name: &str) -> anyhow::Result<String> {
         let catalog_repo = Catalog::get_full_repo_name(repo_name);
-        let deno_config_file = dbang_utils::dbang_dir().join("catalogs/github").join(catalog_repo).join("deno.json");
+        let deno_config_file = dbang_utils::dbang_dir()?.join("catalogs/github").join(catalog_repo).join("deno.json");
         if !deno_config_file.exists() {
-            fs::write(&deno_config_file, "{}").unwrap();
+            fs::write(&deno_config_file, "{}").map_err(|e| DbangError::from_io(&deno_config_file, e))?;
         }
-        String::from(deno_config_file.to_string_lossy())
+        Ok(String::from(deno_config_file.to_string_lossy()))
     }
 
     pub fn get_deno_permissions(&self) -> Vec<String> {
         if let Some(permissions) = &self.permissions {
-            return permissions.iter().map(|x| {
-                if x.starts_with("--") {
-                    x.clone()
-                } else if x.starts_with("-") {
-                    format!("-{}", x)
-                } else if x.contains("allow-") {
-                    format!("--{}", x)
-                } else {
-                    format!("--allow-{}", x)
-                }
-            }).collect();
+            return permissions.iter().map(|x| to_deno_permission(x)).collect();
         }
         return vec![];
     }
 
-    pub fn get_deno_bin_path(&self) -> String {
+    pub fn get_deno_bin_path(&self) -> anyhow::Result<String> {
         if let Some(deno_version) = &self.deno {
-            return String::from(deno_versions::get_deno_binary(deno_version).to_string_lossy());
+            return Ok(String::from(deno_versions::get_deno_binary(deno_version)?.to_string_lossy()));
         }
-        let default_deno = deno_versions::get_default_deno();
+        let default_deno = deno_versions::get_default_deno()?;
         if default_deno.exists() {
-            return String::from(default_deno.to_string_lossy());
+            return Ok(String::from(default_deno.to_string_lossy()));
+        }
+        Ok("deno".to_string())
+    }
+
+    /// Deno version used to run the script, same choice as `get_deno_bin_path`
+    pub fn get_deno_version(&self) -> Option<String> {
+        if let Some(deno_version) = &self.deno {
+            return Some(deno_version.trim_start_matches('v').to_string());
+        }
+        if let Some(deno_version) = deno_versions::get_default_deno_version() {
+            return Some(deno_version);
+        }
+        deno_versions::detect_version("deno")
+    }
+
+    pub fn get_unstable_flags(&self) -> Vec<String> {
+        match &self.unstable {
+            Some(Unstable::Enabled(true)) => vec!["--unstable".to_string()],
+            Some(Unstable::Features(features)) if !features.is_empty() => {
+                let granular = self.get_deno_version()
+                    .map(|version| deno_versions::supports_unstable_features(&version))
+                    .unwrap_or(true);
+                if granular {
+                    features.iter().map(|feature| {
+                        format!("--unstable-{}", feature.trim_start_matches("--unstable-"))
+                    }).collect()
+                } else {
+                    vec!["--unstable".to_string()]
+                }
+            }
+            _ => vec![],
         }
-        return "deno".to_string();
     }
 
     pub fn is_platform_compatible(&self) -> bool {
@@ -117,48 +337,184 @@ impl Artifact {
     }
 }
 
+impl Default for Catalog {
+    fn default() -> Self {
+        Catalog::new()
+    }
+}
+
 impl Catalog {
-    pub fn cache_artifacts(&self, github_user: &str) -> anyhow::Result<()> {
-        for (_k, v) in self.scripts.iter() {
-            deno_cli::cache(&v.get_deno_bin_path(), &v.get_script_http_url(github_user), &v.import_map)?;
+    pub fn new() -> Catalog {
+        Catalog {
+            schema: Some(SCHEMA_URL.to_string()),
+            lazy_cache: None,
+            defaults: None,
+            includes: None,
+            scripts: IndexMap::new(),
+            included_scripts: IndexMap::new(),
+            included_catalogs: vec![],
+        }
+    }
+
+    /// write to dbang-catalog.json, and keep key order and indent of the existing file
+    pub fn write_to_file(&self, dbang_catalog_json_file: &Path) -> anyhow::Result<()> {
+        dbang_utils::write_json_file(dbang_catalog_json_file, self)
+    }
+
+    /// effective artifact of script, with defaults of the catalog applied.
+    /// Included scripts have defaults of their own catalogs applied already
+    pub fn get_artifact(&self, script_name: &str) -> Option<Artifact> {
+        let artifact = self.scripts.get(script_name)?;
+        Some(match &self.defaults {
+            Some(defaults) if !self.included_scripts.contains_key(script_name) => artifact.with_defaults(defaults),
+            _ => artifact.clone(),
+        })
+    }
+
+    /// effective artifacts of all scripts, in the author's order
+    pub fn get_artifacts(&self) -> IndexMap<String, Artifact> {
+        self.scripts.keys()
+            .filter_map(|script_name| self.get_artifact(script_name).map(|artifact| (script_name.clone(), artifact)))
+            .collect()
+    }
+
+    /// catalog with effective artifacts, and defaults are applied to scripts
+    pub fn to_effective(&self) -> Catalog {
+        Catalog {
+            schema: self.schema.clone(),
+            lazy_cache: self.lazy_cache,
+            defaults: None,
+            includes: self.includes.clone(),
+            scripts: self.get_artifacts(),
+            included_scripts: self.included_scripts.clone(),
+            included_catalogs: self.included_catalogs.clone(),
+        }
+    }
+
+    /// scripts with keyword in name or description, in the author's order
+    pub fn search(&self, keyword: &str) -> Vec<(&String, &Artifact)> {
+        let keyword = keyword.to_lowercase();
+        self.scripts.iter().filter(|(name, artifact)| {
+            name.to_lowercase().contains(&keyword)
+                || artifact.description.as_ref().map(|x| x.to_lowercase().contains(&keyword)).unwrap_or(false)
+        }).collect()
+    }
+
+    /// cache scripts concurrently, all scripts or scripts changed since they were cached last time.
+    /// Scripts for other platforms are skipped, and failures are collected in the report
+    pub fn cache_artifacts(&self, repo_name: &str, all: bool) -> anyhow::Result<CacheReport> {
+        let cache_state = deno_cache::CacheState::load(repo_name)?;
+        let artifacts: Vec<(String, Artifact)> = self.get_artifacts().into_iter()
+            .filter(|(_, artifact)| artifact.is_platform_compatible())
+            .collect();
+        // fingerprints fetch scripts in the repo, and they are fetched concurrently too
+        let mut fingerprints = Vec::new();
+        for chunk in artifacts.chunks(CACHE_JOBS) {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = chunk.iter()
+                    .map(|(_, artifact)| scope.spawn(move || deno_cache::fingerprint(artifact, repo_name)))
+                    .collect();
+                fingerprints.extend(handles.into_iter().map(|handle| handle.join().unwrap_or(None)));
+            });
+        }
+        let scripts: Vec<(&String, &Artifact, Option<String>)> = artifacts.iter().zip(fingerprints)
+            .filter(|((script_name, _), fingerprint)| all || cache_state.is_changed(script_name, fingerprint.as_deref()))
+            .map(|((script_name, artifact), fingerprint)| (script_name, artifact, fingerprint))
+            .collect();
+        let total = scripts.len();
+        let finished = AtomicUsize::new(0);
+        let report = Mutex::new(CacheReport::default());
+        let progress = |script_name: &str, result: anyhow::Result<()>, started: Instant| {
+            let index = finished.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut report = report.lock().unwrap();
+            match result {
+                Ok(()) => {
+                    println!("[{}/{}] {} cached in {:.1}s", index, total, script_name, started.elapsed().as_secs_f64());
+                    report.cached.push(script_name.to_string());
+                }
+                Err(e) => {
+                    println!("{}", format!("[{}/{}] {} failed", index, total, script_name).red());
+                    report.failures.push((script_name.to_string(), e.to_string()));
+                }
+            }
         };
-        Ok(())
+        // Deno versions are installed one by one before caching, and never by concurrent jobs
+        let mut jobs = Vec::new();
+        for (script_name, artifact, fingerprint) in scripts {
+            match artifact.get_deno_bin_path() {
+                Ok(deno_bin_path) => jobs.push((script_name, artifact, deno_bin_path, fingerprint)),
+                Err(e) => progress(script_name, Err(e), Instant::now()),
+            }
+        }
+        let job_count = CACHE_JOBS.min(jobs.len());
+        let queue = Mutex::new(jobs.into_iter());
+        std::thread::scope(|scope| {
+            for _ in 0..job_count {
+                scope.spawn(|| loop {
+                    let job = queue.lock().unwrap().next();
+                    let (script_name, artifact, deno_bin_path, fingerprint) = match job {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    let started = Instant::now();
+                    progress(script_name, artifact.cache_with_deno(&deno_bin_path, repo_name, script_name, fingerprint), started);
+                });
+            }
+        });
+        let mut report = report.into_inner().unwrap();
+        report.cached.sort_by_key(|script_name| self.scripts.get_index_of(script_name));
+        report.failures.sort_by_key(|(script_name, _)| self.scripts.get_index_of(script_name));
+        Ok(report)
     }
 
+    /// fetch catalog from GitHub, and resolve its includes
     pub fn fetch_from_github(repo_name: &str) -> anyhow::Result<Catalog> {
         let catalog_full_name = Catalog::get_full_repo_name(repo_name);
-        let url = get_dbang_catalog_url_on_github(&catalog_full_name);
-        let client = Client::new();
-        let response = client.get(&url).send()?;
-        let catalog: Catalog = response.json()?;
-        Ok(catalog)
+        dbang_utils::ensure_online(&format!("Catalog {}", catalog_full_name))?;
+        let data = fetch_catalog_data(&catalog_full_name, "dbang-catalog.json")?;
+        let catalog: Catalog = serde_json::from_slice(&data)
+            .map_err(|e| DbangError::Parse { file: format!("dbang-catalog.json of {}", catalog_full_name), reason: e.to_string() })?;
+        catalog_include::resolve_in_repo(catalog, &catalog_full_name)
     }
 
     pub fn read_from_local(repo_name: &str) -> anyhow::Result<Catalog> {
         let catalog_full_name = Catalog::get_full_repo_name(repo_name);
-        let dbang_catalog_json_file = dbang_utils::dbang_dir()
+        let dbang_catalog_json_file = dbang_utils::dbang_dir()?
             .join("catalogs/github")
-            .join(catalog_full_name)
+            .join(&catalog_full_name)
             .join("dbang-catalog.json");
-        return Catalog::read_from_file(&dbang_catalog_json_file);
+        if !dbang_catalog_json_file.exists() {
+            return Err(DbangError::not_found(format!("Catalog {}", catalog_full_name),
+                                             format!("Please add it by `dbang catalog add {}`", catalog_full_name)).into());
+        }
+        Catalog::read_from_file(&dbang_catalog_json_file)
     }
 
+    /// read catalog from file, and resolve its includes. Local catalogs are saved with includes resolved
     pub fn read_from_file(dbang_catalog_json_file: &Path) -> anyhow::Result<Catalog> {
-        let data = fs::read_to_string(dbang_catalog_json_file)
-            .expect(format!("Unable to read {}", dbang_catalog_json_file.to_string_lossy()).as_str());
+        let catalog = Catalog::read_raw_from_file(dbang_catalog_json_file)?;
+        if catalog.includes.is_none() {
+            return Ok(catalog);
+        }
+        catalog_include::resolve_in_file(catalog, dbang_catalog_json_file)
+    }
+
+    /// read catalog from file as written by the author, and includes are not resolved
+    pub fn read_raw_from_file(dbang_catalog_json_file: &Path) -> anyhow::Result<Catalog> {
+        let data = errors::read_to_string(dbang_catalog_json_file)?;
         let catalog: Catalog = serde_json::from_str(&data)
-            .expect(format!("Unable to parse {}", dbang_catalog_json_file.to_string_lossy()).as_str());
+            .map_err(|e| DbangError::Parse { file: dbang_catalog_json_file.to_string_lossy().to_string(), reason: e.to_string() })?;
         Ok(catalog)
     }
 
     pub fn save(&self, repo_name: &str) -> anyhow::Result<()> {
         let catalog_full_name = Catalog::get_full_repo_name(repo_name);
-        let dbang_catalog_dir = dbang_utils::dbang_dir()
+        let dbang_catalog_dir = dbang_utils::dbang_dir()?
             .join("catalogs/github")
             .join(catalog_full_name);
         std::fs::create_dir_all(&dbang_catalog_dir)?;
         let dbang_catalog_file = dbang_catalog_dir.join("dbang-catalog.json");
-        let json_text = serde_json::to_string(self)?;
+        let json_text = dbang_utils::to_pretty_json(self, "  ")?;
         std::fs::write(&dbang_catalog_file, json_text)?;
         let deno_config_file = dbang_catalog_dir.join("deno.json");
         if !deno_config_file.exists() {
@@ -167,9 +523,35 @@ impl Catalog {
         Ok(())
     }
 
+    /// scripts with changed Deno permissions, compared with old catalog
+    pub fn permission_changes(&self, old: &Catalog) -> Vec<String> {
+        self.get_artifacts().iter()
+            .filter_map(|(script_name, artifact)| {
+                let old_permissions = old.get_artifact(script_name).map(|x| x.get_deno_permissions()).unwrap_or_default();
+                let new_permissions = artifact.get_deno_permissions();
+                if old_permissions == new_permissions {
+                    None
+                } else {
+                    let to_text = |permissions: Vec<String>| if permissions.is_empty() { "none".to_string() } else { permissions.join(" ") };
+                    Some(format!("{}: {} -> {}", script_name, to_text(old_permissions), to_text(new_permissions)))
+                }
+            })
+            .collect()
+    }
+
+    /// permission changes compared with local catalog, none if local catalog doesn't exist,
+    /// and changes are unknown if it's corrupt
+    pub fn local_permission_changes(&self, repo_name: &str) -> anyhow::Result<Vec<String>> {
+        Ok(match Catalog::local_exists(repo_name)? {
+            true => Catalog::read_from_local(repo_name).map(|local| self.permission_changes(&local))
+                .unwrap_or_else(|e| vec![format!("local catalog is unreadable: {}", e)]),
+            false => vec![],
+        })
+    }
+
     pub fn local_exists(repo_name: &str) -> anyhow::Result<bool> {
         let catalog_repo = Catalog::get_full_repo_name(repo_name);
-        let dbang_catalog_json_file = dbang_utils::dbang_dir()
+        let dbang_catalog_json_file = dbang_utils::dbang_dir()?
             .join("catalogs/github")
             .join(catalog_repo)
             .join("dbang-catalog.json");
@@ -178,7 +560,7 @@ impl Catalog {
 
     pub fn delete(repo_name: &str) -> anyhow::Result<()> {
         let catalog_repo = Catalog::get_full_repo_name(repo_name);
-        let dbang_catalog = dbang_utils::dbang_dir()
+        let dbang_catalog = dbang_utils::dbang_dir()?
             .join("catalogs/github")
             .join(catalog_repo);
         fs::remove_dir_all(&dbang_catalog)?;
@@ -186,9 +568,12 @@ impl Catalog {
     }
 
     pub fn list_local() -> anyhow::Result<Vec<String>> {
-        let github_dir = dbang_utils::dbang_dir()
+        let github_dir = dbang_utils::dbang_dir()?
             .join("catalogs")
             .join("github");
+        if !github_dir.exists() {
+            return Ok(Vec::new());
+        }
         let mut users = fs::read_dir(github_dir)?;
         let mut user_list = Vec::new();
         while let Some(file) = users.next() {
@@ -196,12 +581,12 @@ impl Catalog {
             let user_path = user.path();
             if user_path.is_dir() {
                 let github_user = user.file_name();
-                let github_user = github_user.to_str().unwrap();
+                let github_user = github_user.to_string_lossy();
                 let mut repos = fs::read_dir(user_path)?;
                 while let Some(repo) = repos.next() {
                     let repo = repo?;
                     let repo_name = repo.file_name();
-                    let repo_name = repo_name.to_str().unwrap();
+                    let repo_name = repo_name.to_string_lossy();
                     user_list.push(format!("{}/{}", github_user, repo_name));
                 }
             }
@@ -221,23 +606,147 @@ impl Catalog {
 
 pub fn save_remote_dbang_catalog(repo_name: &str) -> anyhow::Result<()> {
     let catalog_full_name = Catalog::get_full_repo_name(repo_name);
-    let url = get_dbang_catalog_url_on_github(&catalog_full_name);
-    let response = Client::builder()
-        .build()?
-        .get(&url)
-        .header("Accept", "application/json")
-        .send()?;
-    let catalog = response.json::<Catalog>()?;
+    let catalog = Catalog::fetch_from_github(&catalog_full_name)?;
     catalog.save(&catalog_full_name)
 }
 
-fn get_dbang_catalog_url_on_github(catalog_full_name: &str) -> String {
-    let github_auth_token = dbang_utils::github_auth_token();
-    return if let Some(token) = github_auth_token {
-        format!("https://{}@raw.githubusercontent.com/{}/HEAD/dbang-catalog.json", token, catalog_full_name)
+/// resolve commit id of catalog repo HEAD by GitHub API, None if not reachable
+pub fn resolve_head_commit(repo_name: &str) -> Option<String> {
+    if dbang_utils::is_offline() {
+        return None;
+    }
+    let catalog_full_name = Catalog::get_full_repo_name(repo_name);
+    let url = format!("{}/repos/{}/commits/HEAD", dbang_utils::github_api_url(), catalog_full_name);
+    let mut request = Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build().ok()?
+        .get(&url)
+        .header("Accept", "application/vnd.github.sha")
+        .header("User-Agent", "dbang");
+    if let Some(token) = dbang_utils::github_auth_token(&url) {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response.text().ok().map(|sha| sha.trim().to_string())
+}
+
+/// Deno lockfile of script, `~/.dbang/catalogs/github/<repo>/locks/<script>.json`
+pub fn get_lock_file(repo_name: &str, script_name: &str) -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?
+        .join("catalogs/github")
+        .join(Catalog::get_full_repo_name(repo_name))
+        .join("locks")
+        .join(format!("{}.json", script_name)))
+}
+
+/// Deno flag of permission, e.g. `net` or `allow-net` to `--allow-net`, and `deny-net` to `--deny-net`
+fn to_deno_permission(permission: &str) -> String {
+    if permission.starts_with("--") {
+        permission.to_string()
+    } else if permission.starts_with("-") {
+        format!("-{}", permission)
+    } else if permission.contains("allow-") || permission.starts_with("deny-") {
+        format!("--{}", permission)
     } else {
-        format!("https://raw.githubusercontent.com/{}/HEAD/dbang-catalog.json", catalog_full_name)
-    };
+        format!("--allow-{}", permission)
+    }
+}
+
+/// result of caching scripts of a catalog
+#[derive(Debug, Default)]
+pub struct CacheReport {
+    /// names of cached scripts, in the catalog's order
+    pub cached: Vec<String>,
+    /// names of scripts failed to cache, with reasons
+    pub failures: Vec<(String, String)>,
+}
+
+impl CacheReport {
+    /// print failures in a table, and return error if any script failed to cache
+    pub fn check(&self, repo_name: &str) -> anyhow::Result<()> {
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        let total = self.cached.len() + self.failures.len();
+        eprintln!("{}", format!("Failed to cache {} of {} scripts:", self.failures.len(), total).red());
+        let width = self.failures.iter().map(|(script_name, _)| script_name.len()).max().unwrap_or_default().max("SCRIPT".len());
+        eprintln!("  {:<width$}  REASON", "SCRIPT", width = width);
+        for (script_name, reason) in &self.failures {
+            eprintln!("  {:<width$}  {}", script_name, reason, width = width);
+        }
+        bail!("Failed to cache scripts of {}, please check them and run `dbang cache warm {}` again",
+              Catalog::get_full_repo_name(repo_name), Catalog::get_full_repo_name(repo_name))
+    }
+}
+
+/// fetch catalog file from GitHub, and the file must be signed if the catalog has a pinned public key
+pub(crate) fn fetch_catalog_data(catalog_full_name: &str, file_name: &str) -> anyhow::Result<Vec<u8>> {
+    let data = fetch_catalog_file(catalog_full_name, file_name)?
+        .ok_or_else(|| DbangError::not_found(format!("{} of {}", file_name, catalog_full_name),
+                                             "Please check the repo name, and dbang-catalog.json should be in the default branch"))?;
+    // signature by one of pinned public keys is required, and error of the most specific key is reported
+    let public_keys = trust::public_keys(catalog_full_name)?;
+    if !public_keys.is_empty() {
+        let bad_signature = |reason: String| DbangError::BadSignature { catalog: catalog_full_name.to_string(), reason };
+        let signature_file_name = format!("{}.minisig", file_name);
+        let signature = fetch_catalog_file(catalog_full_name, &signature_file_name)?
+            .ok_or_else(|| bad_signature(format!("{} not found", signature_file_name)))?;
+        let signature_text = String::from_utf8_lossy(&signature);
+        let trusted_comment = match public_keys[0].verify(&data, &signature_text) {
+            Ok(trusted_comment) => trusted_comment,
+            Err(e) => public_keys[1..].iter()
+                .find_map(|public_key| public_key.verify(&data, &signature_text).ok())
+                .ok_or_else(|| bad_signature(e.to_string()))?,
+        };
+        // signature of other repos or files signed by the same key is refused
+        let signed_file = format!("{}/{}", catalog_full_name, file_name);
+        match catalog_sign::trusted_file(&trusted_comment) {
+            Some(file) if file == signed_file => {}
+            file => return Err(bad_signature(format!("signature is for {}, not {}", file.unwrap_or("unknown file"), signed_file)).into()),
+        }
+        let timestamp = catalog_sign::trusted_timestamp(&trusted_comment)
+            .ok_or_else(|| bad_signature("timestamp missing in trusted comment".to_string()))?;
+        catalog_sign::check_rollback(&signed_file, timestamp)
+            .map_err(|e| bad_signature(e.to_string()))?;
+    }
+    Ok(data)
+}
+
+/// content of file in catalog repo, None if offline or not fetched
+pub(crate) fn fetch_repo_file(catalog_full_name: &str, file_name: &str) -> Option<Vec<u8>> {
+    if dbang_utils::is_offline() {
+        return None;
+    }
+    fetch_catalog_file(catalog_full_name, file_name).ok().flatten()
+}
+
+/// fetch file in catalog repo, None if not found
+fn fetch_catalog_file(catalog_full_name: &str, file_name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let url = get_catalog_file_url_on_github(catalog_full_name, file_name);
+    let response = Client::new()
+        .get(&url)
+        .send()
+        .map_err(|e| DbangError::Network(format!("Unable to fetch {} of {}: {}", file_name, catalog_full_name, e.without_url())))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(DbangError::Network(format!("Unable to fetch {} of {}: HTTP {}", file_name, catalog_full_name, response.status())).into());
+    }
+    let data = response.bytes()
+        .map_err(|e| DbangError::Network(format!("Unable to fetch {} of {}: {}", file_name, catalog_full_name, e.without_url())))?;
+    Ok(Some(data.to_vec()))
+}
+
+fn get_catalog_file_url_on_github(catalog_full_name: &str, file_name: &str) -> String {
+    let url = format!("{}/{}/HEAD/{}", dbang_utils::github_raw_url(), catalog_full_name, file_name);
+    match dbang_utils::github_auth_token(&url) {
+        Some(token) => url.replacen("://", &format!("://{}@", token), 1),
+        None => url,
+    }
 }
 
 #[cfg(test)]
@@ -249,7 +758,7 @@ mod tests {
         //language=json
         let json_text = r#"
         {
-          "aliases": {
+          "scripts": {
             "hello": {
               "script-ref": "hello.ts",
               "description": "Hello world"
@@ -265,46 +774,8 @@ mod tests {
           }
         }"#;
         let catalog: Catalog = serde_json::from_str(json_text).unwrap();
-        println!("catalog = {:?}", catalog);
-    }
-
-    #[test]
-    fn test_save_dbang_catalog() -> anyhow::Result<()> {
-        let github_user = "linux-china";
-        save_remote_dbang_catalog(github_user)?;
-        if Catalog::local_exists(github_user).is_ok() {
-            let catalog = Catalog::read_from_local(github_user)?;
-            println!("catalog = {:?}", catalog);
-        }
-        Ok(())
-    }
-
-    #[test]
-    fn test_fetch_remote_dbang_catalog() -> anyhow::Result<()> {
-        let catalog = Catalog::fetch_from_github("linux-china")?;
-        println!("catalog = {:?}", catalog);
-        Ok(())
-    }
-
-    #[test]
-    fn test_read_local_dbang_catalog() -> anyhow::Result<()> {
-        let catalog = Catalog::read_from_local("linux-china")?;
-        println!("catalog = {:?}", catalog);
-        Ok(())
-    }
-
-    #[test]
-    fn test_get_artifact() {
-        let artifact = Artifact::read_from_local("linux-china", "hello").unwrap();
-        println!("artifact = {:?}", artifact);
-        println!("url = {}", artifact.get_script_http_url("linux-china"));
-    }
-
-    #[test]
-    fn test_list_catalogs() {
-        for user in Catalog::list_local().unwrap() {
-            println!("user = {}", user);
-        }
+        assert_eq!(catalog.scripts.keys().collect::<Vec<_>>(), vec!["hello", "myip"]);
+        assert_eq!(catalog.search("IP").len(), 1);
     }
 
     #[test]
@@ -318,8 +789,10 @@ mod tests {
             unstable: None,
             permissions: None,
             compat: None,
+            merge_permissions: None,
             v8_flags: None,
             args: None,
+            sha256: None,
         };
         if cfg!(target_os = "macos") {
             assert!(artifact.is_platform_compatible());
@@ -327,4 +800,58 @@ mod tests {
             assert!(!artifact.is_platform_compatible());
         }
     }
+
+    #[test]
+    fn test_artifact_diff() {
+        let old_artifact: Artifact = serde_json::from_str(r#"{"script-ref": "myip.ts", "permissions": ["allow-net"]}"#).unwrap();
+        let new_artifact: Artifact = serde_json::from_str(r#"{"script-ref": "myip.ts", "deno": "1.40.0", "permissions": ["allow-net", "allow-env"]}"#).unwrap();
+        assert_eq!(old_artifact.diff(&new_artifact), vec![
+            r#"permissions: ["allow-net"] -> ["allow-net","allow-env"]"#,
+            r#"deno: none -> "1.40.0""#,
+        ]);
+        assert!(new_artifact.diff(&new_artifact).is_empty());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let json_text = r#"{
+          "defaults": {"deno": "1.46.3", "import-map": "import_map.json", "permissions": ["allow-net"]},
+          "scripts": {
+            "hello": {"script-ref": "hello.ts"},
+            "myip": {"script-ref": "myip.ts", "deno": "2.0.0", "permissions": ["allow-env"]},
+            "cat": {"script-ref": "cat.ts", "permissions": ["allow-read", "net"], "merge-permissions": true}
+          }
+        }"#;
+        let catalog: Catalog = serde_json::from_str(json_text).unwrap();
+        let hello = catalog.get_artifact("hello").unwrap();
+        assert_eq!(hello.deno.as_deref(), Some("1.46.3"));
+        assert_eq!(hello.import_map.as_deref(), Some("import_map.json"));
+        assert_eq!(hello.get_deno_permissions(), vec!["--allow-net"]);
+        let myip = catalog.get_artifact("myip").unwrap();
+        assert_eq!(myip.deno.as_deref(), Some("2.0.0"));
+        assert_eq!(myip.get_deno_permissions(), vec!["--allow-env"]);
+        let cat = catalog.get_artifact("cat").unwrap();
+        assert_eq!(cat.get_deno_permissions(), vec!["--allow-net", "--allow-read"]);
+        let mut deny = Artifact::new("deny.ts");
+        deny.permissions = Some(vec!["net".to_string(), "deny-net=example.com".to_string()]);
+        assert_eq!(deny.get_deno_permissions(), vec!["--allow-net", "--deny-net=example.com"]);
+        assert_eq!(cat.merge_permissions, None);
+        assert!(catalog.get_artifact("unknown").is_none());
+        // raw scripts are kept for saving
+        assert!(catalog.scripts["hello"].deno.is_none());
+        assert!(serde_json::to_string(&catalog.to_effective()).unwrap().contains(r#""deno":"1.46.3""#));
+    }
+
+    #[test]
+    fn test_unstable_flags() {
+        let json_text = r#"{"script-ref": "kv.ts", "deno": "1.40.0", "unstable": ["kv", "ffi"]}"#;
+        let artifact: Artifact = serde_json::from_str(json_text).unwrap();
+        assert_eq!(artifact.get_unstable_flags(), vec!["--unstable-kv", "--unstable-ffi"]);
+        let json_text = r#"{"script-ref": "kv.ts", "deno": "1.37.2", "unstable": ["kv"]}"#;
+        let artifact: Artifact = serde_json::from_str(json_text).unwrap();
+        assert_eq!(artifact.get_unstable_flags(), vec!["--unstable"]);
+        let json_text = r#"{"script-ref": "kv.ts", "deno": "1.40.0", "unstable": true}"#;
+        let artifact: Artifact = serde_json::from_str(json_text).unwrap();
+        assert_eq!(artifact.get_unstable_flags(), vec!["--unstable"]);
+    }
 }
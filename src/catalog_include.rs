@@ -0,0 +1,301 @@
+//! Includes of catalogs: scripts of other catalogs are added to a catalog when it's loaded
+//!
+//! An include is a GitHub repo, a URL of dbang-catalog.json, or a path relative to the including catalog,
+//! with an optional prefix for names of included scripts. Script refs and import maps of included scripts
+//! are rebased to files of the included catalog, and catalogs included by a local catalog are read from
+//! local catalogs, so they are accepted by `dbang catalog add` first.
+use std::path::{Path, PathBuf};
+use reqwest::blocking::Client;
+use reqwest::Url;
+use crate::catalog::{self, Catalog};
+use crate::errors::{self, DbangError};
+use crate::{dbang_utils, trust};
+
+/// include refs ending with `.json` or starting with `./` or `../` are paths, e.g. `tools/dbang-catalog.json`
+pub fn is_path(include_ref: &str) -> bool {
+    include_ref.ends_with(".json") || include_ref.starts_with("./") || include_ref.starts_with("../")
+}
+
+fn is_url(include_ref: &str) -> bool {
+    include_ref.starts_with("https://") || include_ref.starts_with("http://")
+}
+
+/// resolve includes of catalog fetched from GitHub repo
+pub fn resolve_in_repo(mut catalog: Catalog, catalog_full_name: &str) -> anyhow::Result<Catalog> {
+    // sources of included scripts are resolved here, and never taken from fetched JSON
+    catalog.included_scripts.clear();
+    resolve(catalog, Location::Repo { repo: catalog_full_name.to_string(), path: "dbang-catalog.json".to_string() })
+}
+
+/// resolve includes of local dbang-catalog.json
+pub fn resolve_in_file(catalog: Catalog, dbang_catalog_json_file: &Path) -> anyhow::Result<Catalog> {
+    let root_dir = dbang_catalog_json_file.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let path = dbang_catalog_json_file.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+    resolve(catalog, Location::File { root_dir, path })
+}
+
+fn resolve(catalog: Catalog, root: Location) -> anyhow::Result<Catalog> {
+    let mut resolver = Resolver { stack: vec![root.key()], included_catalogs: vec![], root: root.clone() };
+    let mut catalog = resolver.resolve_catalog(catalog, &root)?;
+    catalog.included_catalogs = resolver.included_catalogs;
+    Ok(catalog)
+}
+
+/// where a catalog is loaded from
+#[derive(Debug, Clone)]
+enum Location {
+    /// file in GitHub repo, and path is relative to the repo root
+    Repo { repo: String, path: String },
+    Url(Url),
+    /// local file, and path is relative to dir of the root catalog
+    File { root_dir: PathBuf, path: String },
+}
+
+impl Location {
+    /// location of included catalog, and paths are relative to this catalog
+    fn include(&self, include_ref: &str) -> anyhow::Result<Location> {
+        if is_url(include_ref) {
+            let url = Url::parse(include_ref)
+                .map_err(|e| DbangError::Parse { file: self.to_string(), reason: format!("invalid include {}: {}", include_ref, e) })?;
+            return Ok(Location::Url(url));
+        }
+        if !is_path(include_ref) {
+            return Ok(Location::Repo { repo: Catalog::get_full_repo_name(include_ref), path: "dbang-catalog.json".to_string() });
+        }
+        Ok(match self {
+            Location::Repo { repo, path } => Location::Repo { repo: repo.clone(), path: join_path(dir_of(path), include_ref) },
+            Location::Url(url) => Location::Url(url.join(include_ref)
+                .map_err(|e| DbangError::Parse { file: self.to_string(), reason: format!("invalid include {}: {}", include_ref, e) })?),
+            Location::File { root_dir, path } => Location::File { root_dir: root_dir.clone(), path: join_path(dir_of(path), include_ref) },
+        })
+    }
+
+    /// key of catalog for cycle detection
+    fn key(&self) -> String {
+        match self {
+            Location::File { root_dir, path } => {
+                let file = root_dir.join(path);
+                file.canonicalize().unwrap_or(file).to_string_lossy().to_string()
+            }
+            _ => self.to_string(),
+        }
+    }
+
+    /// script ref or import map of this catalog, rebased for the root catalog
+    fn rebase(&self, root: &Location, script_ref: &str) -> String {
+        if ["https://", "http://", "npm:", "jsr:"].iter().any(|prefix| script_ref.starts_with(prefix)) {
+            return script_ref.to_string();
+        }
+        match self {
+            Location::Repo { repo, path } => {
+                let script_path = join_path(dir_of(path), script_ref);
+                match root {
+                    Location::Repo { repo: root_repo, .. } if root_repo == repo => script_path,
+                    _ => format!("{}/{}/HEAD/{}", dbang_utils::github_raw_url(), repo, script_path),
+                }
+            }
+            Location::Url(url) => url.join(script_ref).map(|x| x.to_string()).unwrap_or(script_ref.to_string()),
+            Location::File { path, .. } => join_path(dir_of(path), script_ref),
+        }
+    }
+
+    /// GitHub repo or URL of the catalog for trust rules, None for files in the root repo or local files
+    fn trust_name(&self, root: &Location) -> Option<String> {
+        match (self, root) {
+            (Location::Repo { repo, .. }, Location::Repo { repo: root_repo, .. }) if repo == root_repo => None,
+            (Location::Repo { repo, .. }, _) => Some(repo.clone()),
+            (Location::Url(url), _) => Some(url.to_string()),
+            (Location::File { .. }, _) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Repo { repo, path } if path == "dbang-catalog.json" => write!(f, "{}", repo),
+            Location::Repo { repo, path } => write!(f, "{}/{}", repo, path),
+            Location::Url(url) => write!(f, "{}", url),
+            Location::File { path, .. } => write!(f, "{}", path),
+        }
+    }
+}
+
+struct Resolver {
+    root: Location,
+    /// catalogs being resolved, from the root catalog to the current one
+    stack: Vec<String>,
+    /// GitHub repos and URLs of included catalogs
+    included_catalogs: Vec<String>,
+}
+
+impl Resolver {
+    fn resolve_catalog(&mut self, mut catalog: Catalog, location: &Location) -> anyhow::Result<Catalog> {
+        for include in catalog.includes.take().unwrap_or_default() {
+            let target = location.include(include.catalog())?;
+            let key = target.key();
+            if self.stack.contains(&key) {
+                let reason = format!("include cycle {} -> {}", self.stack.join(" -> "), key);
+                return Err(DbangError::Parse { file: location.to_string(), reason }.into());
+            }
+            if let Some(trust_name) = target.trust_name(&self.root) {
+                if !self.included_catalogs.contains(&trust_name) {
+                    self.included_catalogs.push(trust_name);
+                }
+            }
+            let included = self.load(&target)?;
+            // scripts of pre-resolved local catalogs are rebased too, and only scripts resolved here are rebased already
+            let loaded_scripts: Vec<String> = included.included_scripts.keys().cloned().collect();
+            self.stack.push(key);
+            let included = self.resolve_catalog(included, &target)?;
+            self.stack.pop();
+            for (script_name, mut artifact) in included.get_artifacts() {
+                let name = format!("{}{}", include.prefix(), script_name);
+                // scripts of the catalog and earlier includes win
+                if catalog.scripts.contains_key(&name) {
+                    continue;
+                }
+                let source = included.included_scripts.get(&script_name);
+                if source.is_none() || loaded_scripts.contains(&script_name) {
+                    artifact.script_ref = target.rebase(&self.root, &artifact.script_ref);
+                    artifact.import_map = artifact.import_map.map(|x| target.rebase(&self.root, &x));
+                }
+                let source = source.cloned().unwrap_or(target.to_string());
+                catalog.scripts.insert(name.clone(), artifact);
+                catalog.included_scripts.insert(name, source);
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// included catalog without resolving its includes
+    fn load(&self, location: &Location) -> anyhow::Result<Catalog> {
+        match location {
+            Location::Repo { repo, path } => {
+                trust::ensure_not_denied(repo)?;
+                if let Location::File { .. } = self.root {
+                    return Catalog::read_from_local(repo);
+                }
+                let data = catalog::fetch_catalog_data(repo, path)?;
+                let mut catalog: Catalog = serde_json::from_slice(&data)
+                    .map_err(|e| DbangError::Parse { file: location.to_string(), reason: e.to_string() })?;
+                catalog.included_scripts.clear();
+                Ok(catalog)
+            }
+            Location::Url(url) => {
+                // local catalogs are not approved, and they include accepted catalogs only
+                if let Location::File { .. } = self.root {
+                    return Err(DbangError::PermissionDenied(format!("catalog {} included by local catalog {} is not accepted, \
+                        please include a GitHub repo added by `dbang catalog add`", url, self.root)).into());
+                }
+                // catalogs by URL are not signed, and signed catalogs include signed catalogs only
+                if let Location::Repo { repo, .. } = &self.root {
+                    if !trust::public_keys(repo)?.is_empty() {
+                        return Err(DbangError::BadSignature { catalog: repo.clone(), reason: format!("included catalog {} \
+                            can't be verified, please include a GitHub repo signed by a pinned key", url) }.into());
+                    }
+                }
+                dbang_utils::ensure_online(&format!("Catalog {}", url))?;
+                let data = fetch_url(url)?;
+                let mut catalog: Catalog = serde_json::from_slice(&data)
+                    .map_err(|e| DbangError::Parse { file: url.to_string(), reason: e.to_string() })?;
+                catalog.included_scripts.clear();
+                Ok(catalog)
+            }
+            Location::File { root_dir, path } => {
+                let file = root_dir.join(path);
+                let data = errors::read_to_string(&file)?;
+                serde_json::from_str(&data)
+                    .map_err(|e| DbangError::Parse { file: file.to_string_lossy().to_string(), reason: e.to_string() }.into())
+            }
+        }
+    }
+}
+
+fn fetch_url(url: &Url) -> anyhow::Result<Vec<u8>> {
+    let response = Client::new()
+        .get(url.clone())
+        .send()
+        .map_err(|e| DbangError::Network(format!("Unable to fetch catalog {}: {}", url, e.without_url())))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(DbangError::not_found(format!("Catalog {}", url), "Please check includes of dbang-catalog.json").into());
+    }
+    if !response.status().is_success() {
+        return Err(DbangError::Network(format!("Unable to fetch catalog {}: HTTP {}", url, response.status())).into());
+    }
+    let data = response.bytes()
+        .map_err(|e| DbangError::Network(format!("Unable to fetch catalog {}: {}", url, e.without_url())))?;
+    Ok(data.to_vec())
+}
+
+fn dir_of(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or_default()
+}
+
+fn join_path(dir: &str, path: &str) -> String {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    if dir.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}/{}", dir, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Include;
+
+    #[test]
+    fn test_rebase() {
+        let root = Location::Repo { repo: "linux-china/dbang-catalog".to_string(), path: "dbang-catalog.json".to_string() };
+        let tools = root.include("./tools/dbang-catalog.json").unwrap();
+        assert_eq!(tools.to_string(), "linux-china/dbang-catalog/tools/dbang-catalog.json");
+        assert_eq!(tools.rebase(&root, "hello.ts"), "tools/hello.ts");
+        assert_eq!(tools.trust_name(&root), None);
+        let other = tools.include("my-org").unwrap();
+        assert_eq!(other.to_string(), "my-org/dbang-catalog");
+        assert!(other.rebase(&root, "hello.ts").ends_with("/my-org/dbang-catalog/HEAD/hello.ts"));
+        assert_eq!(other.rebase(&root, "jsr:@std/http"), "jsr:@std/http");
+        let url = root.include("https://example.com/team/dbang-catalog.json").unwrap();
+        assert_eq!(url.rebase(&root, "../hello.ts"), "https://example.com/hello.ts");
+        assert_eq!(url.trust_name(&root).as_deref(), Some("https://example.com/team/dbang-catalog.json"));
+        let include: Vec<Include> = serde_json::from_str(r#"["my-org/tools", {"catalog": "./ops.json", "prefix": "ops-"}]"#).unwrap();
+        assert_eq!((include[0].catalog(), include[0].prefix()), ("my-org/tools", ""));
+        assert_eq!((include[1].catalog(), include[1].prefix()), ("./ops.json", "ops-"));
+    }
+
+    #[test]
+    fn test_resolve_in_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("ops")).unwrap();
+        std::fs::write(dir.path().join("dbang-catalog.json"), r#"{
+          "includes": [{"catalog": "ops/dbang-catalog.json", "prefix": "ops-"}],
+          "scripts": {"hello": {"script-ref": "hello.ts"}, "ops-deploy": {"script-ref": "deploy.ts"}}
+        }"#).unwrap();
+        std::fs::write(dir.path().join("ops/dbang-catalog.json"), r#"{
+          "defaults": {"permissions": ["--allow-net"]},
+          "scripts": {"deploy": {"script-ref": "deploy.ts"}, "status": {"script-ref": "./status.ts"}}
+        }"#).unwrap();
+        let catalog = Catalog::read_from_file(&dir.path().join("dbang-catalog.json")).unwrap();
+        assert_eq!(catalog.scripts.keys().collect::<Vec<_>>(), vec!["hello", "ops-deploy", "ops-status"]);
+        assert_eq!(catalog.scripts["ops-deploy"].script_ref, "deploy.ts");
+        let status = catalog.get_artifact("ops-status").unwrap();
+        assert_eq!(status.script_ref, "ops/status.ts");
+        assert_eq!(status.permissions, Some(vec!["--allow-net".to_string()]));
+        assert_eq!(catalog.included_scripts["ops-status"], "ops/dbang-catalog.json");
+        assert!(catalog.included_catalogs.is_empty());
+        // a catalog including its includer
+        std::fs::write(dir.path().join("ops/dbang-catalog.json"), r#"{
+          "includes": ["../dbang-catalog.json"], "scripts": {}
+        }"#).unwrap();
+        let error = Catalog::read_from_file(&dir.path().join("dbang-catalog.json")).unwrap_err();
+        assert!(error.to_string().contains("include cycle"), "{}", error);
+        // URL includes of local catalogs are refused without fetching
+        std::fs::write(dir.path().join("ops/dbang-catalog.json"), r#"{
+          "includes": ["https://example.com/dbang-catalog.json"], "scripts": {}
+        }"#).unwrap();
+        let error = Catalog::read_from_file(&dir.path().join("dbang-catalog.json")).unwrap_err();
+        assert!(error.to_string().contains("is not accepted"), "{}", error);
+    }
+}
@@ -0,0 +1,72 @@
+//! Scaffold for new catalog repository
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::catalog::{Artifact, Catalog};
+
+const HELLO_TS: &str = r#"const name = Deno.args[0] ?? "World";
+console.log(`Hello ${name}!`);
+"#;
+
+const DENO_JSON: &str = r#"{
+  "tasks": {
+    "hello": "deno run hello.ts"
+  }
+}
+"#;
+
+const README_SECTION: &str = r#"
+# DBang catalog
+
+Scripts in this repository are managed by [DBang](https://dbang.dev), please check `dbang-catalog.json` for details.
+
+```shell
+dbang catalog add <github_user>/<repo>
+dbang run hello@<github_user>/<repo> World
+```
+"#;
+
+/// create dbang-catalog.json, hello.ts, deno.json and README section in dir,
+/// existing files are kept, and return files created or updated
+pub fn init(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let mut changed_files = Vec::new();
+    let dbang_catalog_file = dir.join("dbang-catalog.json");
+    if !dbang_catalog_file.exists() {
+        let mut catalog = Catalog::new();
+        let mut hello = Artifact::new("hello.ts");
+        hello.description = Some("Hello world".to_string());
+        hello.args = Some(vec!["World".to_string()]);
+        catalog.scripts.insert("hello".to_string(), hello);
+        catalog.write_to_file(&dbang_catalog_file)?;
+        changed_files.push(dbang_catalog_file);
+    }
+    for (file_name, content) in [("hello.ts", HELLO_TS), ("deno.json", DENO_JSON)] {
+        let file = dir.join(file_name);
+        if !file.exists() {
+            fs::write(&file, content)?;
+            changed_files.push(file);
+        }
+    }
+    let readme_file = dir.join("README.md");
+    let readme = fs::read_to_string(&readme_file).unwrap_or_default();
+    if !readme.contains("dbang-catalog.json") {
+        fs::write(&readme_file, format!("{}{}", readme, README_SECTION))?;
+        changed_files.push(readme_file);
+    }
+    Ok(changed_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("dbang-init-test");
+        assert_eq!(init(&dir).unwrap().len(), 4);
+        let catalog = Catalog::read_from_file(&dir.join("dbang-catalog.json")).unwrap();
+        assert!(catalog.scripts.contains_key("hello"));
+        assert!(init(&dir).unwrap().is_empty());
+    }
+}
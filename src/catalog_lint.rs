@@ -0,0 +1,543 @@
+//! Lint for dbang-catalog.json, rules follow schema/dbang-catalog.schema.json
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use colored::*;
+use reqwest::blocking::Client;
+use serde_json::Value;
+use crate::catalog::{Artifact, Catalog, Unstable};
+use crate::{audit_log, catalog_include, deno_compat, deno_versions};
+
+const CATALOG_KEYS: &[&str] = &["$schema", "lazy-cache", "defaults", "includes", "scripts", "included-scripts"];
+const INCLUDE_KEYS: &[&str] = &["catalog", "prefix"];
+const DEFAULTS_KEYS: &[&str] = &["import-map", "deno", "unstable", "permissions"];
+const SCRIPT_KEYS: &[&str] = &["script-ref", "description", "compat", "import-map", "deno", "platforms",
+    "unstable", "permissions", "merge-permissions", "v8_flags", "args", "sha256"];
+const PLATFORM_OS: &[&str] = &["macos", "linux", "windows"];
+const PLATFORM_ARCH: &[&str] = &["x86_64", "aarch64", "arm"];
+const PERMISSION_NAMES: &[&str] = &["read", "write", "net", "env", "run", "ffi", "sys", "hrtime", "import", "scripts"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// 1-based line and column in dbang-catalog.json
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct LintIssue {
+    pub severity: Severity,
+    /// path of JSON element, e.g. `scripts.hello.permissions[0]`, empty for the whole catalog
+    pub path: String,
+    pub position: Position,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Warning => "warning".yellow(),
+            Severity::Error => "error".red(),
+        };
+        write!(f, "{}:{}: {}: ", self.position.line, self.position.column, level)?;
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LintOptions {
+    /// catalog repo for remote script refs, None for a local catalog whose script files are checked on disk
+    pub repo_name: Option<String>,
+    /// check script and import map URLs are reachable
+    pub check_urls: bool,
+}
+
+pub fn lint_file(dbang_catalog_file: &Path, options: &LintOptions) -> anyhow::Result<Vec<LintIssue>> {
+    let text = std::fs::read_to_string(dbang_catalog_file)?;
+    Ok(lint_text(&text, dbang_catalog_file.parent(), options))
+}
+
+pub fn lint_text(text: &str, base_dir: Option<&Path>, options: &LintOptions) -> Vec<LintIssue> {
+    let mut linter = Linter { positions: HashMap::new(), issues: Vec::new() };
+    let value: Value = match serde_json::from_str(text) {
+        Ok(value) => value,
+        Err(e) => {
+            linter.issues.push(LintIssue {
+                severity: Severity::Error,
+                path: "".to_string(),
+                position: Position { line: e.line(), column: e.column() },
+                message: format!("invalid JSON: {}", e),
+            });
+            return linter.issues;
+        }
+    };
+    linter.positions = scan_positions(text);
+    linter.check_keys(&value);
+    match serde_json::from_value::<Catalog>(value) {
+        Ok(catalog) => {
+            for (index, include) in catalog.includes.iter().flatten().enumerate() {
+                linter.check_include(&format!("includes[{}]", index), include.catalog(), base_dir, options);
+            }
+            if let Some(defaults) = &catalog.defaults {
+                linter.check_artifact("defaults", &Artifact::new("").with_defaults(defaults), base_dir, options);
+            }
+            // removed behaviour depends on Deno version, which may be from defaults
+            let artifacts = catalog.get_artifacts();
+            for (name, artifact) in catalog.scripts.iter() {
+                let path = format!("scripts.{}", name);
+                linter.check_artifact(&path, artifact, base_dir, options);
+                linter.check_removed_behaviour(&path, &artifacts[name]);
+            }
+        }
+        Err(e) => linter.error("", format!("invalid catalog: {}", e)),
+    }
+    linter.issues.sort_by_key(|issue| issue.position);
+    linter.issues
+}
+
+struct Linter {
+    positions: HashMap<String, Position>,
+    issues: Vec<LintIssue>,
+}
+
+impl Linter {
+    fn add(&mut self, severity: Severity, path: &str, message: String) {
+        // report at the nearest element with known position
+        let mut key = path;
+        let position = loop {
+            if let Some(position) = self.positions.get(key) {
+                break *position;
+            }
+            match key.rfind(['.', '[']) {
+                Some(index) => key = &key[..index],
+                None => break Position { line: 1, column: 1 },
+            }
+        };
+        self.issues.push(LintIssue { severity, path: path.to_string(), position, message });
+    }
+
+    fn error(&mut self, path: &str, message: String) {
+        self.add(Severity::Error, path, message);
+    }
+
+    fn warn(&mut self, path: &str, message: String) {
+        self.add(Severity::Warning, path, message);
+    }
+
+    fn check_keys(&mut self, value: &Value) {
+        if let Some(catalog) = value.as_object() {
+            self.check_unknown_keys("", catalog.keys(), CATALOG_KEYS);
+            if let Some(defaults) = catalog.get("defaults").and_then(|x| x.as_object()) {
+                self.check_unknown_keys("defaults", defaults.keys(), DEFAULTS_KEYS);
+            }
+            if let Some(includes) = catalog.get("includes").and_then(|x| x.as_array()) {
+                for (index, include) in includes.iter().enumerate() {
+                    if let Some(include) = include.as_object() {
+                        self.check_unknown_keys(&format!("includes[{}]", index), include.keys(), INCLUDE_KEYS);
+                    }
+                }
+            }
+            if let Some(scripts) = catalog.get("scripts").and_then(|x| x.as_object()) {
+                for (name, script) in scripts {
+                    if let Some(script) = script.as_object() {
+                        self.check_unknown_keys(&format!("scripts.{}", name), script.keys(), SCRIPT_KEYS);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_unknown_keys<'a>(&mut self, parent: &str, keys: impl Iterator<Item=&'a String>, known_keys: &[&str]) {
+        for key in keys {
+            if !known_keys.contains(&key.as_str()) {
+                let path = if parent.is_empty() { key.clone() } else { format!("{}.{}", parent, key) };
+                let message = match suggest_key(key, known_keys) {
+                    Some(known_key) => format!("unknown key `{}`, did you mean `{}`?", key, known_key),
+                    None => format!("unknown key `{}`", key),
+                };
+                self.error(&path, message);
+            }
+        }
+    }
+
+    fn check_artifact(&mut self, path: &str, artifact: &Artifact, base_dir: Option<&Path>, options: &LintOptions) {
+        if let Some(platforms) = &artifact.platforms {
+            for (index, platform) in platforms.iter().enumerate() {
+                if !is_valid_platform(platform) {
+                    self.error(&format!("{}.platforms[{}]", path, index),
+                               format!("invalid platform `{}`, format is `os` or `os-arch`, os: {}, arch: {}",
+                                       platform, PLATFORM_OS.join("/"), PLATFORM_ARCH.join("/")));
+                }
+            }
+        }
+        if let Some(permissions) = &artifact.permissions {
+            let deno_permissions = artifact.get_deno_permissions();
+            for (index, permission) in permissions.iter().enumerate() {
+                if !is_valid_permission(&deno_permissions[index]) {
+                    self.error(&format!("{}.permissions[{}]", path, index),
+                               format!("invalid permission `{}`", permission));
+                }
+            }
+        }
+        if let Some(deno_version) = &artifact.deno {
+            let version_path = format!("{}.deno", path);
+            match deno_versions::parse_version(deno_version) {
+                None => self.error(&version_path, format!("invalid Deno version `{}`", deno_version)),
+                Some(version) if version < deno_versions::MIN_SUPPORTED_VERSION => {
+                    let (major, minor, patch) = deno_versions::MIN_SUPPORTED_VERSION;
+                    self.error(&version_path, format!("Deno {} is not supported, minimal version is {}.{}.{}",
+                                                      deno_version, major, minor, patch));
+                }
+                _ => {}
+            }
+        }
+        let is_remote = |reference: &str| reference.contains("://") || reference.starts_with("npm:") || reference.starts_with("jsr:");
+        if let Some(sha256) = &artifact.sha256 {
+            let sha256_path = format!("{}.sha256", path);
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                self.error(&sha256_path, format!("invalid SHA-256 `{}`, 64 hex digits expected", sha256));
+            } else if let (None, Some(base_dir)) = (&options.repo_name, base_dir) {
+                let script_sha256 = Some(&artifact.script_ref).filter(|x| !is_remote(x))
+                    .and_then(|script_ref| audit_log::file_sha256(&base_dir.join(script_ref)));
+                if script_sha256.is_some_and(|script_sha256| !script_sha256.eq_ignore_ascii_case(sha256)) {
+                    self.error(&sha256_path, format!("SHA-256 of `{}` doesn't match", artifact.script_ref));
+                }
+            }
+        }
+        // no script-ref in defaults
+        let mut references = Vec::new();
+        if !artifact.script_ref.is_empty() {
+            references.push(("script-ref", artifact.script_ref.clone()));
+        }
+        if let Some(import_map) = &artifact.import_map {
+            references.push(("import-map", import_map.clone()));
+        }
+        for (key, reference) in references {
+            let reference_path = format!("{}.{}", path, key);
+            if options.repo_name.is_none() && !is_remote(&reference) {
+                if let Some(base_dir) = base_dir {
+                    if !base_dir.join(&reference).exists() {
+                        self.error(&reference_path, format!("file `{}` not found", reference));
+                    }
+                }
+            }
+            if options.check_urls {
+                let url = match &options.repo_name {
+                    Some(repo_name) if key == "script-ref" => artifact.get_script_http_url(repo_name),
+                    Some(repo_name) => artifact.get_import_map_http_url(repo_name),
+                    None => reference.clone(),
+                };
+                if url.starts_with("http://") || url.starts_with("https://") {
+                    if let Err(message) = check_url(&url) {
+                        self.warn(&reference_path, format!("{} is unreachable: {}", url, message));
+                    }
+                }
+            }
+        }
+    }
+
+    /// included catalog files of a local catalog should exist
+    fn check_include(&mut self, path: &str, include_ref: &str, base_dir: Option<&Path>, options: &LintOptions) {
+        if options.repo_name.is_some() || !catalog_include::is_path(include_ref) || include_ref.contains("://") {
+            return;
+        }
+        if let Some(base_dir) = base_dir {
+            if !base_dir.join(include_ref).exists() {
+                self.error(path, format!("included catalog `{}` not found", include_ref));
+            }
+        }
+    }
+
+    /// warn about scripts relying on flags or config semantics removed from newer Deno
+    #[allow(deprecated)]
+    fn check_removed_behaviour(&mut self, path: &str, artifact: &Artifact) {
+        let deno_version = artifact.deno.as_deref();
+        let runs_on = deno_version.unwrap_or("latest");
+        if artifact.compat == Some(true) {
+            if let Some((_, hint)) = deno_compat::removed_flag(deno_version, "--compat") {
+                self.warn(&format!("{}.compat", path), format!("`compat` is ignored by Deno {}: {}", runs_on, hint));
+            }
+        }
+        if artifact.unstable == Some(Unstable::Enabled(true)) {
+            if let Some((_, hint)) = deno_compat::removed_flag(deno_version, "--unstable") {
+                self.warn(&format!("{}.unstable", path), format!("`unstable: true` is ignored by Deno {}: {}", runs_on, hint));
+            }
+        }
+        for (index, permission) in artifact.get_deno_permissions().iter().enumerate() {
+            if let Some((_, hint)) = deno_compat::removed_flag(deno_version, permission) {
+                self.warn(&format!("{}.permissions[{}]", path, index), format!("`{}` is removed from Deno {}: {}", permission, runs_on, hint));
+            }
+        }
+        if artifact.import_map.is_some() && !deno_compat::supports_config_with_import_map(deno_version) {
+            self.warn(&format!("{}.import-map", path),
+                      format!("`import-map` runs with `--no-config` on Deno {}, scripts no longer share the catalog's web storage", runs_on));
+        }
+    }
+}
+
+fn suggest_key<'a>(key: &str, known_keys: &[&'a str]) -> Option<&'a str> {
+    let normalize = |text: &str| text.replace('_', "-").trim_end_matches('s').to_lowercase();
+    known_keys.iter().find(|known_key| normalize(known_key) == normalize(key)).copied()
+}
+
+fn is_valid_platform(platform: &str) -> bool {
+    match platform.split_once('-') {
+        Some((os, arch)) => PLATFORM_OS.contains(&os) && PLATFORM_ARCH.contains(&arch),
+        None => PLATFORM_OS.contains(&platform),
+    }
+}
+
+fn is_valid_permission(deno_permission: &str) -> bool {
+    let flag = deno_permission.split('=').next().unwrap_or(deno_permission);
+    if flag == "--allow-all" {
+        return !deno_permission.contains('=');
+    }
+    flag.strip_prefix("--allow-")
+        .or_else(|| flag.strip_prefix("--deny-"))
+        .map(|name| PERMISSION_NAMES.contains(&name))
+        .unwrap_or(false)
+}
+
+fn check_url(url: &str) -> Result<(), String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client.get(url).send().map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", response.status()))
+    }
+}
+
+/// scan positions of object keys and array elements, key is the path of JSON element
+fn scan_positions(text: &str) -> HashMap<String, Position> {
+    let mut scanner = Scanner {
+        chars: text.chars().collect(),
+        index: 0,
+        position: Position { line: 1, column: 1 },
+        positions: HashMap::new(),
+    };
+    scanner.value("");
+    scanner.positions
+}
+
+/// minimal JSON scanner, only used for text already parsed by serde_json
+struct Scanner {
+    chars: Vec<char>,
+    index: usize,
+    position: Position,
+    positions: HashMap<String, Position>,
+}
+
+impl Scanner {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    fn value(&mut self, path: &str) {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(path),
+            Some('[') => self.array(path),
+            Some('"') => {
+                self.string();
+            }
+            Some(_) => {
+                while matches!(self.peek(), Some(c) if !matches!(c, ',' | '}' | ']') && !c.is_whitespace()) {
+                    self.bump();
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn object(&mut self, path: &str) {
+        self.bump();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('"') => {
+                    let position = self.position;
+                    let key = self.string();
+                    let key_path = if path.is_empty() { key } else { format!("{}.{}", path, key) };
+                    self.positions.insert(key_path.clone(), position);
+                    self.skip_whitespace();
+                    self.bump(); // ':'
+                    self.value(&key_path);
+                }
+                Some(',') => {
+                    self.bump();
+                }
+                _ => {
+                    self.bump(); // '}'
+                    return;
+                }
+            }
+        }
+    }
+
+    fn array(&mut self, path: &str) {
+        self.bump();
+        let mut index = 0;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(']') | None => {
+                    self.bump();
+                    return;
+                }
+                Some(',') => {
+                    self.bump();
+                }
+                _ => {
+                    let element_path = format!("{}[{}]", path, index);
+                    self.positions.insert(element_path.clone(), self.position);
+                    self.value(&element_path);
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let start = self.index;
+        self.bump();
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '"' => break,
+                _ => {}
+            }
+        }
+        let literal: String = self.chars[start..self.index].iter().collect();
+        serde_json::from_str(&literal).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_text() {
+        //language=json
+        let json_text = r#"{
+  "aliases": {},
+  "scripts": {
+    "hello": {
+      "script-ref": "https://example.com/hello.ts",
+      "deno": "1.2",
+      "platforms": ["macos", "linux-ppc"],
+      "permissions": ["allow-net", "allow-nothing"],
+      "v8-flags": "--max-old-space-size=1024"
+    },
+    "kv": {
+      "script-ref": "https://example.com/kv.ts",
+      "deno": "2.0.0",
+      "compat": true,
+      "unstable": true,
+      "permissions": ["allow-hrtime"]
+    }
+  }
+}"#;
+        let issues = lint_text(json_text, None, &LintOptions::default());
+        let find = |path: &str| issues.iter().find(|issue| issue.path == path).unwrap();
+        assert_eq!(find("aliases").position, Position { line: 2, column: 3 });
+        assert_eq!(find("scripts.hello.deno").severity, Severity::Error);
+        assert_eq!(find("scripts.hello.platforms[1]").position, Position { line: 7, column: 30 });
+        assert_eq!(find("scripts.hello.permissions[1]").severity, Severity::Error);
+        assert!(find("scripts.hello.v8-flags").message.contains("`v8_flags`"));
+        assert_eq!(find("scripts.kv.compat").severity, Severity::Warning);
+        assert_eq!(find("scripts.kv.unstable").severity, Severity::Warning);
+        assert_eq!(find("scripts.kv.permissions[0]").severity, Severity::Warning);
+        assert_eq!(issues.len(), 8);
+    }
+
+    #[test]
+    fn test_lint_defaults() {
+        let json_text = r#"{
+  "defaults": {"deno": "2.0.0", "unstable": true, "permissions": ["allow-nothing"], "args": []},
+  "scripts": {
+    "hello": {"script-ref": "https://example.com/hello.ts", "merge-permissions": true}
+  }
+}"#;
+        let issues = lint_text(json_text, None, &LintOptions::default());
+        let find = |path: &str| issues.iter().find(|issue| issue.path == path).unwrap();
+        assert_eq!(find("defaults.args").severity, Severity::Error);
+        assert_eq!(find("defaults.permissions[0]").severity, Severity::Error);
+        // Deno version from defaults
+        assert_eq!(find("scripts.hello.unstable").severity, Severity::Warning);
+        assert_eq!(issues.len(), 3);
+    }
+
+    #[test]
+    fn test_lint_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("ops.json"), r#"{"scripts": {}}"#).unwrap();
+        let json_text = r#"{
+  "includes": ["my-org/tools", "./ops.json", {"catalog": "./missing.json", "prefix": "x-", "alias": "y"}],
+  "scripts": {}
+}"#;
+        let issues = lint_text(json_text, Some(dir.path()), &LintOptions::default());
+        let find = |path: &str| issues.iter().find(|issue| issue.path == path).unwrap();
+        assert!(find("includes[2]").message.contains("`./missing.json` not found"));
+        assert!(find("includes[2].alias").message.contains("unknown key"));
+        assert_eq!(issues.len(), 2);
+    }
+
+    #[test]
+    fn test_lint_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hello.ts"), "console.log('Hello');").unwrap();
+        let json_text = r#"{
+  "scripts": {
+    "hello": {"script-ref": "hello.ts", "sha256": "0000000000000000000000000000000000000000000000000000000000000000"},
+    "myip": {"script-ref": "https://example.com/myip.ts", "sha256": "abc"}
+  }
+}"#;
+        let issues = lint_text(json_text, Some(dir.path()), &LintOptions::default());
+        let find = |path: &str| issues.iter().find(|issue| issue.path == path).unwrap();
+        assert!(find("scripts.hello.sha256").message.contains("doesn't match"));
+        assert!(find("scripts.myip.sha256").message.contains("64 hex digits"));
+        assert_eq!(issues.len(), 2);
+    }
+
+    #[test]
+    fn test_lint_invalid_json() {
+        let issues = lint_text("{\n  \"scripts\": {,}\n}", None, &LintOptions::default());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].position.line, 2);
+    }
+}
@@ -0,0 +1,323 @@
+//! Ed25519 signatures of dbang-catalog.json, in minisign format
+//!
+//! Signature is `dbang-catalog.json.minisig` next to dbang-catalog.json, and keys are compatible with
+//! unencrypted keys of minisign, so catalog authors can sign with `minisign -S` too. The trusted comment
+//! names the signed file with its repo, e.g. `file:linux-china/dbang-catalog/dbang-catalog.json`, so a signature
+//! can't be replayed for other repos or files signed by the same key.
+//! Timestamps of verified signatures are kept in `~/.dbang/signatures.json`, and older signatures are refused
+//! to prevent rollback to old signed catalogs.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use blake2::{Blake2b, Blake2b512, Digest};
+use blake2::digest::consts::U32;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand_core::{OsRng, RngCore};
+use crate::dbang_utils;
+use crate::errors::{self, DbangError};
+
+/// file name of signature, next to dbang-catalog.json
+pub const SIGNATURE_FILE_NAME: &str = "dbang-catalog.json.minisig";
+
+const SIG_ALG: &[u8; 2] = b"Ed";
+/// signature of BLAKE2b-512 hash of the file, default of minisign
+const SIG_ALG_HASHED: &[u8; 2] = b"ED";
+const KDF_NONE: &[u8; 2] = &[0, 0];
+const CHK_ALG: &[u8; 2] = b"B2";
+const UNTRUSTED_COMMENT: &str = "untrusted comment: ";
+const TRUSTED_COMMENT: &str = "trusted comment: ";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicKey {
+    pub key_id: [u8; 8],
+    pub key: VerifyingKey,
+}
+
+pub struct SecretKey {
+    pub key_id: [u8; 8],
+    pub key: SigningKey,
+}
+
+/// base64 line of key or signature file, and comment lines are skipped
+fn decode_base64_line(text: &str, what: &str) -> anyhow::Result<Vec<u8>> {
+    let line = text.lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with(UNTRUSTED_COMMENT))
+        .ok_or_else(|| anyhow!("Empty {}", what))?;
+    BASE64.decode(line).map_err(|e| anyhow!("Invalid {}: {}", what, e))
+}
+
+fn key_id_hex(key_id: &[u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*key_id))
+}
+
+impl PublicKey {
+    /// parse public key in base64, or content of minisign public key file
+    pub fn parse(text: &str) -> anyhow::Result<PublicKey> {
+        let data = decode_base64_line(text, "public key")?;
+        if data.len() != 42 || &data[0..2] != SIG_ALG {
+            bail!("Invalid public key, an Ed25519 key in minisign format expected");
+        }
+        let key_id: [u8; 8] = data[2..10].try_into()?;
+        let key = VerifyingKey::from_bytes(data[10..42].try_into()?)?;
+        Ok(PublicKey { key_id, key })
+    }
+
+    /// public key in base64 or path of public key file
+    pub fn from_arg(value: &str) -> anyhow::Result<PublicKey> {
+        let path = dbang_utils::expand_home(value)?;
+        if path.is_file() {
+            PublicKey::parse(&errors::read_to_string(&path)?)
+        } else {
+            PublicKey::parse(value)
+        }
+    }
+
+    pub fn key_id(&self) -> String {
+        key_id_hex(&self.key_id)
+    }
+
+    pub fn to_base64(&self) -> String {
+        let mut data = Vec::with_capacity(42);
+        data.extend_from_slice(SIG_ALG);
+        data.extend_from_slice(&self.key_id);
+        data.extend_from_slice(self.key.as_bytes());
+        BASE64.encode(data)
+    }
+
+    pub fn to_file_text(&self) -> String {
+        format!("{}minisign public key {}\n{}\n", UNTRUSTED_COMMENT, self.key_id(), self.to_base64())
+    }
+
+    /// verify signature file of data, including the trusted comment, and return the trusted comment
+    pub fn verify(&self, data: &[u8], signature_text: &str) -> anyhow::Result<String> {
+        let mut lines = signature_text.lines().map(|line| line.trim_end()).filter(|line| !line.is_empty());
+        let signature_line = lines.find(|line| !line.starts_with(UNTRUSTED_COMMENT))
+            .ok_or_else(|| anyhow!("Empty signature"))?;
+        let signature_data = BASE64.decode(signature_line).map_err(|e| anyhow!("Invalid signature: {}", e))?;
+        if signature_data.len() != 74 {
+            bail!("Invalid signature");
+        }
+        if signature_data[2..10] != self.key_id {
+            bail!("Signed by key {}, but trusted key is {}", key_id_hex(signature_data[2..10].try_into()?), self.key_id());
+        }
+        let signature = Signature::from_slice(&signature_data[10..74])?;
+        let verified = match &signature_data[0..2] {
+            alg if alg == SIG_ALG_HASHED => self.key.verify(&Blake2b512::digest(data), &signature),
+            alg if alg == SIG_ALG => self.key.verify(data, &signature),
+            _ => bail!("Unsupported signature algorithm"),
+        };
+        verified.map_err(|_| anyhow!("Signature does not match the content"))?;
+        let trusted_comment = lines.next()
+            .and_then(|line| line.strip_prefix(TRUSTED_COMMENT))
+            .ok_or_else(|| anyhow!("Trusted comment missing in signature"))?;
+        let global_signature_data = BASE64.decode(lines.next().unwrap_or_default())
+            .map_err(|e| anyhow!("Invalid signature of trusted comment: {}", e))?;
+        let global_signature = Signature::from_slice(&global_signature_data)
+            .map_err(|_| anyhow!("Invalid signature of trusted comment"))?;
+        let mut global_data = signature_data[10..74].to_vec();
+        global_data.extend_from_slice(trusted_comment.as_bytes());
+        self.key.verify(&global_data, &global_signature)
+            .map_err(|_| anyhow!("Signature of trusted comment does not match"))?;
+        Ok(trusted_comment.to_string())
+    }
+}
+
+impl SecretKey {
+    pub fn generate() -> SecretKey {
+        let mut key_id = [0u8; 8];
+        let mut seed = [0u8; 32];
+        OsRng.fill_bytes(&mut key_id);
+        OsRng.fill_bytes(&mut seed);
+        SecretKey { key_id, key: SigningKey::from_bytes(&seed) }
+    }
+
+    fn checksum(&self) -> Vec<u8> {
+        let mut hasher = Blake2b::<U32>::new();
+        hasher.update(SIG_ALG);
+        hasher.update(self.key_id);
+        hasher.update(self.key.to_keypair_bytes());
+        hasher.finalize().to_vec()
+    }
+
+    /// parse unencrypted minisign secret key file
+    pub fn parse(text: &str) -> anyhow::Result<SecretKey> {
+        let data = decode_base64_line(text, "secret key")?;
+        if data.len() != 158 || &data[0..2] != SIG_ALG {
+            bail!("Invalid secret key, an Ed25519 key in minisign format expected");
+        }
+        if &data[2..4] != KDF_NONE {
+            bail!("Encrypted secret key is not supported, please sign it by `minisign -S -m dbang-catalog.json`");
+        }
+        let key_id: [u8; 8] = data[54..62].try_into()?;
+        let key = SigningKey::from_keypair_bytes(data[62..126].try_into()?)
+            .map_err(|_| anyhow!("Invalid secret key, public key does not match"))?;
+        let secret_key = SecretKey { key_id, key };
+        if secret_key.checksum() != data[126..158] {
+            bail!("Invalid secret key, checksum mismatch");
+        }
+        Ok(secret_key)
+    }
+
+    pub fn to_file_text(&self) -> String {
+        let mut data = Vec::with_capacity(158);
+        data.extend_from_slice(SIG_ALG);
+        data.extend_from_slice(KDF_NONE);
+        data.extend_from_slice(CHK_ALG);
+        data.extend_from_slice(&[0u8; 48]); // kdf salt, opslimit and memlimit, unused without encryption
+        data.extend_from_slice(&self.key_id);
+        data.extend_from_slice(&self.key.to_keypair_bytes());
+        data.extend_from_slice(&self.checksum());
+        format!("{}minisign secret key {}\n{}\n", UNTRUSTED_COMMENT, key_id_hex(&self.key_id), BASE64.encode(data))
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey { key_id: self.key_id, key: self.key.verifying_key() }
+    }
+
+    /// sign data of file in repo, e.g. `linux-china/dbang-catalog/dbang-catalog.json`, and return content of signature file
+    pub fn sign(&self, data: &[u8], signed_file: &str) -> String {
+        let signature = self.key.sign(&Blake2b512::digest(data)).to_bytes();
+        let mut signature_data = Vec::with_capacity(74);
+        signature_data.extend_from_slice(SIG_ALG_HASHED);
+        signature_data.extend_from_slice(&self.key_id);
+        signature_data.extend_from_slice(&signature);
+        let trusted_comment = format!("timestamp:{}\tfile:{}\thashed", chrono::Utc::now().timestamp(), signed_file);
+        let mut global_data = signature.to_vec();
+        global_data.extend_from_slice(trusted_comment.as_bytes());
+        let global_signature = self.key.sign(&global_data).to_bytes();
+        format!("{}signature from dbang secret key {}\n{}\n{}{}\n{}\n", UNTRUSTED_COMMENT, key_id_hex(&self.key_id),
+                BASE64.encode(signature_data), TRUSTED_COMMENT, trusted_comment, BASE64.encode(global_signature))
+    }
+}
+
+/// default secret key to sign catalogs, `~/.dbang/keys/dbang.key`
+pub fn default_secret_key_path() -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?.join("keys").join("dbang.key"))
+}
+
+/// public key file next to secret key, e.g. `dbang.pub` for `dbang.key`
+pub fn public_key_path(secret_key_path: &Path) -> PathBuf {
+    secret_key_path.with_extension("pub")
+}
+
+/// generate key pair, and the secret key is readable by owner only
+pub fn generate_key_pair(secret_key_path: &Path) -> anyhow::Result<PublicKey> {
+    if secret_key_path.exists() {
+        bail!("{} already exists, please remove it before generating a new key", secret_key_path.to_string_lossy());
+    }
+    if let Some(parent) = secret_key_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let secret_key = SecretKey::generate();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(secret_key_path)
+        .and_then(|mut file| file.write_all(secret_key.to_file_text().as_bytes()))
+        .map_err(|e| DbangError::from_io(secret_key_path, e))?;
+    let public_key = secret_key.public_key();
+    fs::write(public_key_path(secret_key_path), public_key.to_file_text())?;
+    Ok(public_key)
+}
+
+/// sign dbang-catalog.json as `signed_file` of repo, and write signature file next to it
+pub fn sign_file(catalog_file: &Path, signed_file: &str, secret_key_path: &Path) -> anyhow::Result<(PathBuf, PublicKey)> {
+    if !secret_key_path.exists() {
+        return Err(DbangError::not_found(format!("Secret key {}", secret_key_path.to_string_lossy()),
+                                         "Please use `dbang catalog sign --generate-key` to generate a key pair").into());
+    }
+    let secret_key = SecretKey::parse(&errors::read_to_string(secret_key_path)?)?;
+    let data = fs::read(catalog_file).map_err(|e| DbangError::from_io(catalog_file, e))?;
+    let file_name = catalog_file.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let signature_file = catalog_file.with_file_name(format!("{}.minisig", file_name));
+    fs::write(&signature_file, secret_key.sign(&data, signed_file)).map_err(|e| DbangError::from_io(&signature_file, e))?;
+    Ok((signature_file, secret_key.public_key()))
+}
+
+/// field in trusted comment of signature, e.g. `timestamp:1700000000\tfile:linux-china/dbang-catalog/dbang-catalog.json\thashed`
+fn trusted_field<'a>(trusted_comment: &'a str, name: &str) -> Option<&'a str> {
+    trusted_comment.split('\t')
+        .find_map(|item| item.strip_prefix(name).and_then(|x| x.strip_prefix(':')))
+        .map(|value| value.trim())
+}
+
+/// timestamp in trusted comment of signature
+pub fn trusted_timestamp(trusted_comment: &str) -> Option<i64> {
+    trusted_field(trusted_comment, "timestamp").and_then(|timestamp| timestamp.parse().ok())
+}
+
+/// signed file with repo in trusted comment of signature
+pub fn trusted_file(trusted_comment: &str) -> Option<&str> {
+    trusted_field(trusted_comment, "file")
+}
+
+fn signatures_file() -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?.join("signatures.json"))
+}
+
+/// refuse signature older than the last verified signature of `<catalog>/<file>`, and remember newer timestamp
+pub fn check_rollback(signed_file: &str, timestamp: i64) -> anyhow::Result<()> {
+    let file = signatures_file()?;
+    let mut timestamps: BTreeMap<String, i64> = if file.exists() {
+        serde_json::from_str(&errors::read_to_string(&file)?)
+            .map_err(|e| DbangError::Parse { file: file.to_string_lossy().to_string(), reason: e.to_string() })?
+    } else {
+        BTreeMap::new()
+    };
+    match timestamps.get(signed_file) {
+        Some(last_timestamp) if timestamp < *last_timestamp => {
+            let to_text = |timestamp: i64| chrono::DateTime::from_timestamp(timestamp, 0).map(|x| x.to_rfc3339()).unwrap_or_else(|| timestamp.to_string());
+            bail!("Signature at {} is older than verified signature at {}, rollback of {} refused",
+                  to_text(timestamp), to_text(*last_timestamp), signed_file);
+        }
+        Some(last_timestamp) if timestamp == *last_timestamp => Ok(()),
+        _ => {
+            timestamps.insert(signed_file.to_string(), timestamp);
+            fs::create_dir_all(dbang_utils::dbang_dir()?)?;
+            fs::write(&file, serde_json::to_string_pretty(&timestamps)?).map_err(|e| DbangError::from_io(&file, e))?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let secret_key = SecretKey::generate();
+        let public_key = secret_key.public_key();
+        let data = br#"{"scripts": {}}"#;
+        let signature = secret_key.sign(data, "linux-china/dbang-catalog/dbang-catalog.json");
+        let trusted_comment = public_key.verify(data, &signature).unwrap();
+        assert!(trusted_timestamp(&trusted_comment).is_some_and(|timestamp| timestamp > 0));
+        assert_eq!(trusted_file(&trusted_comment), Some("linux-china/dbang-catalog/dbang-catalog.json"));
+        assert!(public_key.verify(br#"{"scripts": {"evil": {}}}"#, &signature).is_err());
+        let other_key = SecretKey::generate().public_key();
+        assert!(other_key.verify(data, &signature).is_err());
+        // trusted comment can't be changed
+        let tampered = signature.replace("file:linux-china/dbang-catalog/", "file:other/dbang-catalog/");
+        assert!(public_key.verify(data, &tampered).is_err());
+    }
+
+    #[test]
+    fn test_key_round_trip() {
+        let secret_key = SecretKey::generate();
+        let parsed = SecretKey::parse(&secret_key.to_file_text()).unwrap();
+        assert_eq!(parsed.public_key(), secret_key.public_key());
+        let public_key = secret_key.public_key();
+        assert_eq!(PublicKey::parse(&public_key.to_file_text()).unwrap(), public_key);
+        assert_eq!(PublicKey::parse(&public_key.to_base64()).unwrap(), public_key);
+        assert!(PublicKey::parse("RWQ=").is_err());
+    }
+}
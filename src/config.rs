@@ -0,0 +1,134 @@
+//! DBang config, saved in ~/.dbang/config.json
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::dbang_utils;
+
+/// keys for `dbang config set/unset`
+pub const CONFIG_KEYS: &[&str] = &["bin-dir", "update-check-interval"];
+
+/// default interval of checking new versions of DBang and Deno
+const DEFAULT_UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Config {
+    /// default bin dir for apps, default is ~/.dbang/bin
+    #[serde(rename = "bin-dir", skip_serializing_if = "Option::is_none")]
+    pub bin_dir: Option<String>,
+    /// interval of update checks, such as `12h` or `7d`, and `0` to disable update checks
+    #[serde(rename = "update-check-interval", skip_serializing_if = "Option::is_none")]
+    pub update_check_interval: Option<String>,
+}
+
+fn get_config_file() -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?.join("config.json"))
+}
+
+pub fn load() -> anyhow::Result<Config> {
+    let config_file = get_config_file()?;
+    if config_file.exists() {
+        let data = std::fs::read_to_string(config_file)?;
+        Ok(serde_json::from_str(&data)?)
+    } else {
+        Ok(Config::default())
+    }
+}
+
+pub fn save(config: &Config) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dbang_utils::dbang_dir()?)?;
+    dbang_utils::write_json_file(&get_config_file()?, config)
+}
+
+impl Config {
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "bin-dir" => self.bin_dir.clone(),
+            "update-check-interval" => self.update_check_interval.clone(),
+            _ => None,
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: Option<String>) -> anyhow::Result<()> {
+        match key {
+            "bin-dir" => {
+                // relative bin dir is resolved against current dir, and `~` is kept as is
+                self.bin_dir = match value {
+                    Some(bin_dir) if !bin_dir.starts_with('~') && Path::new(&bin_dir).is_relative() => {
+                        Some(std::path::absolute(&bin_dir)?.to_string_lossy().to_string())
+                    }
+                    bin_dir => bin_dir,
+                };
+            }
+            "update-check-interval" => {
+                if let Some(interval) = &value {
+                    parse_interval(interval)?;
+                }
+                self.update_check_interval = value;
+            }
+            _ => anyhow::bail!("Unknown config key {}, available keys: {}", key, CONFIG_KEYS.join(", ")),
+        }
+        Ok(())
+    }
+
+    /// bin dir for apps, default is ~/.dbang/bin
+    pub fn get_bin_dir(&self) -> anyhow::Result<PathBuf> {
+        match &self.bin_dir {
+            Some(bin_dir) => dbang_utils::expand_home(bin_dir),
+            None => dbang_utils::dbang_bin_dir(),
+        }
+    }
+
+    /// interval of update checks, None if update checks are disabled
+    pub fn get_update_check_interval(&self) -> Option<Duration> {
+        let interval = match &self.update_check_interval {
+            Some(interval) => parse_interval(interval).unwrap_or(DEFAULT_UPDATE_CHECK_INTERVAL),
+            None => DEFAULT_UPDATE_CHECK_INTERVAL,
+        };
+        if interval.is_zero() { None } else { Some(interval) }
+    }
+}
+
+/// parse interval such as `30m`, `12h` or `7d`, and number without unit is hours
+pub fn parse_interval(text: &str) -> anyhow::Result<Duration> {
+    let text = text.trim();
+    let (number, unit_secs) = match text.char_indices().last() {
+        Some((index, 's')) => (&text[..index], 1),
+        Some((index, 'm')) => (&text[..index], 60),
+        Some((index, 'h')) => (&text[..index], 60 * 60),
+        Some((index, 'd')) => (&text[..index], 24 * 60 * 60),
+        _ => (text, 60 * 60),
+    };
+    let invalid_interval = || anyhow::anyhow!("Invalid interval {}, please use format like 30m, 12h or 7d", text);
+    let number: u64 = number.parse().map_err(|_| invalid_interval())?;
+    let secs = number.checked_mul(unit_secs).ok_or_else(invalid_interval)?;
+    Ok(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_config() {
+        let mut config = Config::default();
+        config.set("bin-dir", Some("~/bin".to_string())).unwrap();
+        assert_eq!(config.get("bin-dir").as_deref(), Some("~/bin"));
+        assert_eq!(config.get_bin_dir().unwrap(), dirs::home_dir().unwrap().join("bin"));
+        config.set("bin-dir", Some("bin".to_string())).unwrap();
+        assert_eq!(config.get_bin_dir().unwrap(), std::env::current_dir().unwrap().join("bin"));
+        assert!(config.set("unknown", None).is_err());
+        assert!(config.set("update-check-interval", Some("soon".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_update_check_interval() {
+        assert_eq!(parse_interval("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_interval("7d").unwrap(), Duration::from_secs(7 * 24 * 60 * 60));
+        assert_eq!(parse_interval("12").unwrap(), Duration::from_secs(12 * 60 * 60));
+        assert!(parse_interval("999999999999999999d").unwrap_err().to_string().contains("Invalid interval"));
+        let mut config = Config::default();
+        assert_eq!(config.get_update_check_interval(), Some(DEFAULT_UPDATE_CHECK_INTERVAL));
+        config.set("update-check-interval", Some("0".to_string())).unwrap();
+        assert_eq!(config.get_update_check_interval(), None);
+    }
+}
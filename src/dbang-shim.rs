@@ -1,34 +1,20 @@
-mod dbang_utils;
-mod aliases;
-mod catalog;
-mod deno_cli;
-mod deno_versions;
+use std::path::Path;
+use dbang::{dbang_utils, errors, Runner};
+
+/// env var to print debug info of dbang-shim
+const DBANG_SHIM_VERBOSE: &str = "DBANG_SHIM_VERBOSE";
 
 pub fn main() {
-    let mut alias: String = std::env::args().nth(0).unwrap();
-    if alias.contains("/") {
-        alias = alias.split("/").last().unwrap().to_string();
-    }
+    let program = std::env::args().next().unwrap_or_default();
+    let app_name = Path::new(&program)
+        .file_name()
+        .map(|name| name.to_string_lossy().trim_end_matches(".exe").to_string())
+        .unwrap_or(program);
     let script_args = std::env::args().skip(1).collect::<Vec<String>>();
     let script_args: Vec<&str> = script_args.iter().map(std::ops::Deref::deref).collect();
-    if let Some(script_name) = aliases::find_script_name_by_alias(&alias) {
-        dbang_run(&script_name, &script_args).unwrap();
-    } else {
-        println!("{} not found!", alias);
+    let verbose = dbang_utils::env_flag(DBANG_SHIM_VERBOSE);
+    match Runner::new().verbose(verbose).run_app(&app_name, &script_args) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => std::process::exit(errors::report(&e.context(app_name.clone()), verbose)),
     }
 }
-
-fn dbang_run(script_full_name: &str, script_args: &[&str]) -> anyhow::Result<()> {
-    let artifact_parts: Vec<&str> = script_full_name.split("@").collect();
-    let repo_name = artifact_parts[1];
-    let script_name = artifact_parts[0];
-    let artifact = catalog::Artifact::read_from_local(repo_name, script_name).unwrap();
-    deno_cli::run(repo_name, &artifact, script_args, false)?;
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn test_find_script_name() {}
-}
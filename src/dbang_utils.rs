@@ -1,21 +1,97 @@
+use std::io;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::{Command};
+use serde::Serialize;
+use serde_json::Value;
+use crate::errors::DbangError;
 
-pub fn dbang_dir() -> PathBuf {
-    let home_dir: PathBuf = dirs::home_dir().unwrap();
-    Path::new(&home_dir)
-        .join(".dbang")
+/// env var to override DBang dir, default is ~/.dbang
+pub const DBANG_HOME: &str = "DBANG_HOME";
+/// env vars to override base URLs of GitHub and Deno releases, such as a mirror or a local server for tests
+pub const DBANG_GITHUB_RAW_URL: &str = "DBANG_GITHUB_RAW_URL";
+pub const DBANG_GITHUB_API_URL: &str = "DBANG_GITHUB_API_URL";
+pub const DBANG_DENO_DOWNLOAD_URL: &str = "DBANG_DENO_DOWNLOAD_URL";
+
+pub fn dbang_dir() -> anyhow::Result<PathBuf> {
+    if let Some(dbang_home) = std::env::var_os(DBANG_HOME).filter(|x| !x.is_empty()) {
+        return Ok(PathBuf::from(dbang_home));
+    }
+    Ok(home_dir()?.join(".dbang"))
 }
 
-pub fn github_auth_token() -> Option<String> {
-    if let Ok(tokens) = std::env::var("DENO_AUTH_TOKENS") {
-        for pair in tokens.split(";") {
-            if pair.contains("@raw.githubusercontent.com") {
-                return pair.split("@").nth(0).map(|s| s.to_string());
-            }
+/// home dir of current user, and it's an error if it can't be found
+pub fn home_dir() -> anyhow::Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| DbangError::not_found("Home directory", "Please set HOME, or DBANG_HOME for DBang dir").into())
+}
+
+fn base_url(env_name: &str, default_url: &str) -> String {
+    std::env::var(env_name).ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or(default_url.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// base URL of raw files on GitHub
+pub fn github_raw_url() -> String {
+    base_url(DBANG_GITHUB_RAW_URL, "https://raw.githubusercontent.com")
+}
+
+pub fn github_api_url() -> String {
+    base_url(DBANG_GITHUB_API_URL, "https://api.github.com")
+}
+
+/// base URL of Deno releases, and zip files are in `<base>/v<version>/`
+pub fn deno_download_url() -> String {
+    base_url(DBANG_DENO_DOWNLOAD_URL, "https://github.com/denoland/deno/releases/download")
+}
+
+/// default bin dir for apps and default Deno
+pub fn dbang_bin_dir() -> anyhow::Result<PathBuf> {
+    Ok(dbang_dir()?.join("bin"))
+}
+
+/// find dbang-shim next to current executable, or on PATH
+pub fn find_dbang_shim() -> Option<PathBuf> {
+    let shim_name = if cfg!(target_os = "windows") { "dbang-shim.exe" } else { "dbang-shim" };
+    if let Some(shim_path) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(|dir| dir.join(shim_name))) {
+        if shim_path.exists() {
+            return Some(shim_path);
         }
     }
-    None
+    which::which("dbang-shim").ok()
+}
+
+/// expand `~` at the beginning of path to home dir
+pub fn expand_home(path: &str) -> anyhow::Result<PathBuf> {
+    if path == "~" {
+        home_dir()
+    } else if let Some(relative_path) = path.strip_prefix("~/") {
+        Ok(home_dir()?.join(relative_path))
+    } else {
+        Ok(PathBuf::from(path))
+    }
+}
+
+/// hosts of GitHub to send the token to, and mirrors by DBANG_GITHUB_RAW_URL or DBANG_GITHUB_API_URL never get it
+const GITHUB_TOKEN_HOSTS: [&str; 2] = ["raw.githubusercontent.com", "api.github.com"];
+
+/// token of raw.githubusercontent.com in DENO_AUTH_TOKENS, only for URL on GitHub hosts
+pub fn github_auth_token(url: &str) -> Option<String> {
+    let host = reqwest::Url::parse(url).ok()?.host_str()?.to_string();
+    if !GITHUB_TOKEN_HOSTS.contains(&host.as_str()) {
+        return None;
+    }
+    parse_github_auth_token(&std::env::var("DENO_AUTH_TOKENS").ok()?)
+}
+
+/// DENO_AUTH_TOKENS is `token@host` pairs separated by `;`
+fn parse_github_auth_token(tokens: &str) -> Option<String> {
+    tokens.split(';')
+        .filter_map(|pair| pair.trim().rsplit_once('@'))
+        .find(|(_, host)| *host == "raw.githubusercontent.com")
+        .map(|(token, _)| token.to_string())
 }
 
 pub fn open_url(url: &str) -> anyhow::Result<()> {
@@ -37,13 +113,128 @@ pub fn open_url(url: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// env var to run DBang without network access, same as `dbang --offline`
+pub const DBANG_OFFLINE: &str = "DBANG_OFFLINE";
+
+/// env var is set and not `0` or `false`
+pub fn env_flag(name: &str) -> bool {
+    is_truthy(std::env::var(name).ok().as_deref())
+}
+
+fn is_truthy(value: Option<&str>) -> bool {
+    matches!(value, Some(value) if !value.is_empty() && value != "0" && !value.eq_ignore_ascii_case("false"))
+}
+
+pub fn is_offline() -> bool {
+    env_flag(DBANG_OFFLINE)
+}
+
+/// fail fast in offline mode, and `what` is the resource not cached locally
+pub fn ensure_online(what: &str) -> anyhow::Result<()> {
+    if is_offline() {
+        return Err(DbangError::Offline(what.to_string()).into());
+    }
+    Ok(())
+}
+
+/// prompt question with `y/n` and return true if user answers yes
+pub fn confirm(question: &str) -> anyhow::Result<bool> {
+    print!("{}  y/n > ", question);
+    io::stdout().flush()?;
+    let mut buffer = String::new();
+    io::stdin().read_line(&mut buffer)?;
+    Ok(buffer.trim().starts_with('y'))
+}
+
+/// detect indent of JSON text, default is 2 spaces
+pub fn detect_json_indent(json_text: &str) -> String {
+    json_text.lines()
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .find(|indent| !indent.is_empty())
+        .unwrap_or("  ")
+        .to_string()
+}
+
+/// reorder object keys of `value` following `original`, and new keys are kept at the end
+pub fn reorder_json_like(value: &mut Value, original: &Value) {
+    match (value, original) {
+        (Value::Object(object), Value::Object(original_object)) => {
+            let mut reordered = serde_json::Map::new();
+            for key in original_object.keys() {
+                if let Some(item) = object.remove(key) {
+                    reordered.insert(key.clone(), item);
+                }
+            }
+            reordered.append(object);
+            for (key, item) in reordered.iter_mut() {
+                if let Some(original_item) = original_object.get(key) {
+                    reorder_json_like(item, original_item);
+                }
+            }
+            *object = reordered;
+        }
+        (Value::Array(array), Value::Array(original_array)) => {
+            for (item, original_item) in array.iter_mut().zip(original_array.iter()) {
+                reorder_json_like(item, original_item);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// pretty JSON text with indent and trailing newline
+pub fn to_pretty_json<T: Serialize>(value: &T, indent: &str) -> anyhow::Result<String> {
+    let mut buffer = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+    value.serialize(&mut serializer)?;
+    buffer.push(b'\n');
+    Ok(String::from_utf8(buffer)?)
+}
+
+/// write value as pretty JSON, and keep key order and indent of the existing file
+pub fn write_json_file<T: Serialize>(json_file: &Path, value: &T) -> anyhow::Result<()> {
+    let mut json_value = serde_json::to_value(value)?;
+    let mut indent = "  ".to_string();
+    if let Ok(original_text) = std::fs::read_to_string(json_file) {
+        indent = detect_json_indent(&original_text);
+        if let Ok(original_value) = serde_json::from_str::<Value>(&original_text) {
+            reorder_json_like(&mut json_value, &original_value);
+        }
+    }
+    std::fs::write(json_file, to_pretty_json(&json_value, &indent)?)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_is_truthy() {
+        assert!(is_truthy(Some("1")));
+        assert!(is_truthy(Some("true")));
+        assert!(!is_truthy(Some("0")));
+        assert!(!is_truthy(Some("false")));
+        assert!(!is_truthy(None));
+    }
+
+    #[test]
+    fn test_reorder_json_like() {
+        let original: Value = serde_json::from_str(r#"{"scripts": {"b": {"deno": "1.40.0", "script-ref": "b.ts"}, "a": {}}}"#).unwrap();
+        let mut value: Value = serde_json::from_str(r#"{"scripts": {"a": {}, "c": {}, "b": {"script-ref": "b.ts", "deno": "1.40.0"}}}"#).unwrap();
+        reorder_json_like(&mut value, &original);
+        assert_eq!(value.to_string(), r#"{"scripts":{"b":{"deno":"1.40.0","script-ref":"b.ts"},"a":{},"c":{}}}"#);
+        assert_eq!(detect_json_indent("{\n    \"scripts\": {}\n}"), "    ");
+    }
+
     #[test]
     fn test_github_auth_token() {
-        let token = github_auth_token();
-        println!("{:?}", token.ok_or("not found"));
+        assert_eq!(parse_github_auth_token("abc@deno.land;secret@raw.githubusercontent.com").as_deref(), Some("secret"));
+        assert_eq!(parse_github_auth_token("abc@deno.land"), None);
+        assert_eq!(parse_github_auth_token("secret@raw.githubusercontent.com.evil.com"), None);
+        // mirrors never get the token
+        assert_eq!(github_auth_token("https://mirror.example.com/linux-china/dbang-catalog/HEAD/dbang-catalog.json"), None);
+        assert_eq!(github_auth_token("http://127.0.0.1:8080/raw"), None);
     }
 }
@@ -0,0 +1,181 @@
+//! Deno cache of catalogs: every catalog has its own `DENO_DIR` in `~/.dbang/catalogs/github/<repo>/deno_dir`
+//!
+//! Fingerprints of cached scripts are saved in `dbang-cache.json` of the dir, and warming a catalog caches
+//! the scripts changed since they were cached only. Deno commands of a catalog always run with its `DENO_DIR`.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::catalog::{self, Artifact, Catalog};
+use crate::{dbang_utils, errors};
+
+/// env var of Deno cache dir
+pub const DENO_DIR: &str = "DENO_DIR";
+const CACHE_STATE_FILE_NAME: &str = "dbang-cache.json";
+static CACHE_STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// dedicated `DENO_DIR` of catalog
+pub fn get_deno_dir(repo_name: &str) -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?
+        .join("catalogs/github")
+        .join(Catalog::get_full_repo_name(repo_name))
+        .join("deno_dir"))
+}
+
+/// fingerprints of cached scripts, and empty fingerprint is for script cached without known content
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CacheState {
+    pub scripts: BTreeMap<String, String>,
+}
+
+impl CacheState {
+    pub fn load(repo_name: &str) -> anyhow::Result<CacheState> {
+        let state_file = get_deno_dir(repo_name)?.join(CACHE_STATE_FILE_NAME);
+        if !state_file.exists() {
+            return Ok(CacheState::default());
+        }
+        // broken state file means scripts should be cached again
+        Ok(serde_json::from_str(&errors::read_to_string(&state_file)?).unwrap_or_default())
+    }
+
+    pub fn save(&self, repo_name: &str) -> anyhow::Result<()> {
+        let deno_dir = get_deno_dir(repo_name)?;
+        fs::create_dir_all(&deno_dir)?;
+        fs::write(deno_dir.join(CACHE_STATE_FILE_NAME), dbang_utils::to_pretty_json(self, "  ")?)?;
+        Ok(())
+    }
+
+    /// script is not cached, or changed since it was cached
+    pub fn is_changed(&self, script_name: &str, fingerprint: Option<&str>) -> bool {
+        fingerprint.is_none() || self.scripts.get(script_name).map(|x| x.as_str()) != fingerprint
+    }
+}
+
+/// save fingerprint of cached script, and scripts without fingerprint are always treated as changed
+pub fn record_cached(repo_name: &str, script_name: &str, fingerprint: Option<String>) -> anyhow::Result<()> {
+    // scripts are cached concurrently, and the state file is updated one by one
+    let _guard = CACHE_STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut cache_state = CacheState::load(repo_name)?;
+    cache_state.scripts.insert(script_name.to_string(), fingerprint.unwrap_or_default());
+    cache_state.save(repo_name)
+}
+
+/// script cached in Deno cache of the catalog, changed or not
+pub fn is_cached(repo_name: &str, script_name: &str) -> bool {
+    CacheState::load(repo_name).map(|cache_state| cache_state.scripts.contains_key(script_name)).unwrap_or(false)
+}
+
+/// remote module is in Deno cache of the catalog, at `<DENO_DIR>/remote/<scheme>/<host>/<sha256 of path>`,
+/// or in `deps` dir of Deno 1. None for npm and jsr specifiers, whose cache layout is not checked
+pub fn is_module_cached(repo_name: &str, module_url: &str) -> Option<bool> {
+    let url = reqwest::Url::parse(module_url).ok().filter(|url| ["https", "http"].contains(&url.scheme()))?;
+    let host = match url.port() {
+        Some(port) => format!("{}_PORT{}", url.host_str()?, port),
+        None => url.host_str()?.to_string(),
+    };
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    let file_name = format!("{:x}", Sha256::digest(path.as_bytes()));
+    let deno_dir = get_deno_dir(repo_name).ok()?;
+    Some(["remote", "deps"].iter().any(|dir| deno_dir.join(dir).join(url.scheme()).join(&host).join(&file_name).exists()))
+}
+
+/// SHA-256 of artifact, with content of script and import map in the catalog repo.
+/// None if a file in the repo is unknown, e.g. in offline mode
+pub fn fingerprint(artifact: &Artifact, repo_name: &str) -> Option<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(artifact).ok()?);
+    let catalog_full_name = Catalog::get_full_repo_name(repo_name);
+    let repo_files = std::iter::once(artifact.script_ref.as_str()).chain(artifact.import_map.as_deref());
+    for file_name in repo_files.filter(|x| !is_remote(x)) {
+        hasher.update(catalog::fetch_repo_file(&catalog_full_name, file_name)?);
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+fn is_remote(script_ref: &str) -> bool {
+    ["https://", "http://", "npm:", "jsr:"].iter().any(|prefix| script_ref.starts_with(prefix))
+}
+
+/// total size of files in dir, 0 if the dir doesn't exist
+pub fn dir_size(dir: &Path) -> u64 {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries.flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// remove Deno cache of catalog, and return freed size
+pub fn clean(repo_name: &str) -> anyhow::Result<u64> {
+    let deno_dir = get_deno_dir(repo_name)?;
+    if !deno_dir.exists() {
+        return Ok(0);
+    }
+    let size = dir_size(&deno_dir);
+    fs::remove_dir_all(&deno_dir)?;
+    Ok(size)
+}
+
+/// human-readable size, e.g. `12.3 MB`
+pub fn format_size(size: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, units[0])
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        let remote_artifact = Artifact::new("https://deno.land/std/examples/welcome.ts");
+        let welcome_fingerprint = fingerprint(&remote_artifact, "linux-china");
+        assert!(welcome_fingerprint.is_some());
+        let mut changed_artifact = remote_artifact.clone();
+        changed_artifact.permissions = Some(vec!["allow-net".to_string()]);
+        assert_ne!(fingerprint(&changed_artifact, "linux-china"), welcome_fingerprint);
+        let mut cache_state = CacheState::default();
+        assert!(cache_state.is_changed("welcome", welcome_fingerprint.as_deref()));
+        cache_state.scripts.insert("welcome".to_string(), welcome_fingerprint.clone().unwrap());
+        assert!(!cache_state.is_changed("welcome", welcome_fingerprint.as_deref()));
+        assert!(cache_state.is_changed("welcome", None));
+        cache_state.scripts.insert("welcome".to_string(), String::new());
+        assert!(cache_state.is_changed("welcome", None));
+    }
+
+    #[test]
+    fn test_is_module_cached() {
+        assert_eq!(is_module_cached("linux-china", "npm:cowsay@1.5.0"), None);
+        let url = "http://127.0.0.1:8080/raw/linux-china/dbang-catalog/HEAD/hello.ts";
+        assert_eq!(is_module_cached("dbang-test-missing/catalog", url), Some(false));
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MB");
+    }
+}
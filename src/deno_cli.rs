@@ -1,34 +1,26 @@
 use std::path::Path;
 use std::process::{Command, Output, Stdio};
-use crate::catalog::Artifact;
+use std::time::Instant;
+use crate::audit_log::{self, AuditEntry, AuditEvent};
+use crate::catalog::{Artifact, Catalog};
+use crate::{catalog, dbang_utils, deno_cache, deno_compat};
+use crate::errors::DbangError;
 
-pub fn run(repo_name: &str, artifact: &Artifact, args: &[&str], verbose: bool) -> anyhow::Result<Output> {
-    let mut command = Command::new(artifact.get_deno_bin_path());
+pub fn run(repo_name: &str, script_name: &str, artifact: &Artifact, args: &[&str], verbose: bool) -> anyhow::Result<Output> {
+    let deno_version = artifact.get_deno_version();
+    let import_map = artifact.import_map.as_ref().map(|_| artifact.get_import_map_http_url(repo_name));
+    let deno_bin_path = artifact.get_deno_bin_path()?;
+    let script_url = artifact.get_script_http_url(repo_name);
+    let mut command = Command::new(&deno_bin_path);
     command.arg("run").arg("--no-check").arg("--cached-only");
-    if let Some(unstable) = artifact.unstable {
-        if unstable {
-            command.arg("--unstable");
-        }
-    }
-    if let Some(permissions) = &artifact.permissions {
-        command.args(permissions);
-    }
-    if artifact.import_map.is_some() {
-        command.arg("--import-map");
-        command.arg(artifact.get_import_map_http_url(repo_name));
+    command.args(deno_flags(artifact, &import_map, deno_version.as_deref()));
+    command.args(config_flags(repo_name, artifact, &import_map, deno_version.as_deref())?);
+    // lockfile written when the script cached, and missing for catalogs cached by old DBang
+    let lock_file = catalog::get_lock_file(repo_name, script_name)?;
+    if lock_file.exists() {
+        command.args(deno_compat::lock_flags(deno_version.as_deref(), &lock_file.to_string_lossy()));
     }
-    if artifact.v8_flags.is_some() {
-        command.arg("--v8-flags");
-        command.arg(artifact.v8_flags.as_ref().unwrap());
-    }
-    if let Some(compat) = artifact.compat {
-        if compat {
-            command.arg("--compat");
-        }
-    }
-    command.arg("--config");
-    command.arg(artifact.get_deno_config(repo_name));
-    command.arg(artifact.get_script_http_url(repo_name));
+    command.arg(&script_url);
     if !args.is_empty() {
         command.args(args);
     } else {
@@ -37,41 +29,32 @@ pub fn run(repo_name: &str, artifact: &Artifact, args: &[&str], verbose: bool) -
         }
     }
     if verbose {
-        println!("[dbang] command line:  {:?}", command);
+        eprintln!("[dbang] command line:  {:?}", command);
     }
+    let started = Instant::now();
     let output = command
         .envs(std::env::vars())
+        .env(deno_cache::DENO_DIR, deno_cache::get_deno_dir(repo_name)?)
         .stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
-        .output()?;
+        .output()
+        .map_err(spawn_error)?;
+    let mut entry = AuditEntry::new(AuditEvent::Run).catalog(&Catalog::get_full_repo_name(repo_name)).script(script_name);
+    entry.sha256 = cached_script_sha256(repo_name, &deno_bin_path, &script_url);
+    entry.url = Some(script_url);
+    audit_run(entry, artifact, deno_version, &output, started);
     Ok(output)
 }
 
-pub fn run_local(working_dir: &Path, artifact: &Artifact, args: &[&str], verbose: bool) -> anyhow::Result<Output> {
-    let mut command = Command::new(artifact.get_deno_bin_path());
+pub fn run_local(working_dir: &Path, script_name: &str, artifact: &Artifact, args: &[&str], verbose: bool) -> anyhow::Result<Output> {
+    let deno_version = artifact.get_deno_version();
+    let mut command = Command::new(artifact.get_deno_bin_path()?);
     command.arg("run").arg("--no-check");
-    if let Some(unstable) = artifact.unstable {
-        if unstable {
-            command.arg("--unstable");
-        }
-    }
-    if artifact.permissions.is_some() {
-        command.args(artifact.get_deno_permissions());
-    }
-    if artifact.import_map.is_some() {
-        command.arg("--import-map");
-        command.arg(artifact.import_map.as_ref().unwrap());
-    }
-    if artifact.v8_flags.is_some() {
-        command.arg("--v8-flags");
-        command.arg(artifact.v8_flags.as_ref().unwrap());
-    }
-    if let Some(compat) = artifact.compat {
-        if compat {
-            command.arg("--compat");
-        }
+    if dbang_utils::is_offline() {
+        command.arg("--cached-only");
     }
+    command.args(deno_flags(artifact, &artifact.import_map, deno_version.as_deref()));
     command.arg(&artifact.script_ref);
     if !args.is_empty() {
         command.args(args);
@@ -81,22 +64,134 @@ pub fn run_local(working_dir: &Path, artifact: &Artifact, args: &[&str], verbose
         }
     }
     if verbose {
-        println!("[dbang] command line:  {:?}", command);
+        eprintln!("[dbang] command line:  {:?}", command);
     }
+    let started = Instant::now();
     let output = command
         .current_dir(working_dir)
         .stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
-        .output()?;
+        .output()
+        .map_err(spawn_error)?;
+    let mut entry = AuditEntry::new(AuditEvent::Run).script(script_name);
+    let is_remote = ["https://", "http://", "npm:", "jsr:"].iter().any(|prefix| artifact.script_ref.starts_with(prefix));
+    if !is_remote {
+        entry.sha256 = audit_log::file_sha256(&working_dir.join(&artifact.script_ref));
+    }
+    entry.url = Some(artifact.script_ref.clone());
+    entry.detail = Some(format!("local catalog {}", working_dir.to_string_lossy()));
+    audit_run(entry, artifact, deno_version, &output, started);
+    Ok(output)
+}
+
+/// record script run with permissions, Deno version, exit code and duration
+fn audit_run(mut entry: AuditEntry, artifact: &Artifact, deno_version: Option<String>, output: &Output, started: Instant) {
+    entry.permissions = artifact.get_deno_permissions();
+    entry.deno = deno_version;
+    entry.exit_code = output.status.code();
+    entry.duration_ms = Some(started.elapsed().as_millis() as u64);
+    entry.record();
+}
+
+/// SHA-256 of script content in Deno cache, and the local path is from `deno info --json`
+pub(crate) fn cached_script_sha256(repo_name: &str, deno_bin_path: &str, script_url: &str) -> Option<String> {
+    let output = Command::new(deno_bin_path)
+        .arg("info").arg("--json").arg(script_url)
+        .envs(std::env::vars())
+        .env(deno_cache::DENO_DIR, deno_cache::get_deno_dir(repo_name).ok()?)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output().ok()?;
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    module_local_path(&info, script_url).and_then(|local| audit_log::file_sha256(Path::new(local)))
+}
+
+/// local path of module in `deno info --json` output, following redirects of the specifier, None if it's not found
+fn module_local_path<'a>(info: &'a serde_json::Value, specifier: &'a str) -> Option<&'a str> {
+    let redirect = |specifier: &str| info.get("redirects").and_then(|redirects| redirects.get(specifier)).and_then(|x| x.as_str());
+    let mut specifier = specifier;
+    let mut redirects = 0;
+    while let Some(target) = redirect(specifier) {
+        // redirect loop finds nothing
+        redirects += 1;
+        if redirects > 10 {
+            return None;
+        }
+        specifier = target;
+    }
+    info.get("modules")?.as_array()?.iter()
+        .find(|module| module.get("specifier").and_then(|x| x.as_str()) == Some(specifier))
+        .and_then(|module| module.get("local")?.as_str())
+}
+
+/// compile script to a standalone executable by `deno compile`, default args of script are embedded
+pub fn compile(repo_name: &str, artifact: &Artifact, output_file: &Path, target: Option<&str>, verbose: bool) -> anyhow::Result<Output> {
+    let deno_version = artifact.get_deno_version();
+    let import_map = artifact.import_map.as_ref().map(|_| artifact.get_import_map_http_url(repo_name));
+    let mut command = Command::new(artifact.get_deno_bin_path()?);
+    command.arg("compile").arg("--no-check").arg("--output").arg(output_file);
+    if dbang_utils::is_offline() {
+        command.arg("--cached-only");
+    }
+    if let Some(target) = target {
+        command.arg("--target").arg(target);
+    }
+    command.args(deno_flags(artifact, &import_map, deno_version.as_deref()));
+    command.args(config_flags(repo_name, artifact, &import_map, deno_version.as_deref())?);
+    command.arg(artifact.get_script_http_url(repo_name));
+    if let Some(default_args) = &artifact.args {
+        command.args(default_args);
+    }
+    if verbose {
+        eprintln!("[dbang] command line:  {:?}", command);
+    }
+    let output = command
+        .envs(std::env::vars())
+        .env(deno_cache::DENO_DIR, deno_cache::get_deno_dir(repo_name)?)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(spawn_error)?;
     Ok(output)
 }
 
-pub fn cache(deno_bin_path: &str, script_name: &str, import_map: &Option<String>) -> anyhow::Result<Output> {
+/// `--config` with deno.json of catalog, or `--no-config` for import map on Deno 2
+fn config_flags(repo_name: &str, artifact: &Artifact, import_map: &Option<String>, deno_version: Option<&str>) -> anyhow::Result<Vec<String>> {
+    if import_map.is_none() || deno_compat::supports_config_with_import_map(deno_version) {
+        Ok(vec!["--config".to_string(), artifact.get_deno_config(repo_name)?])
+    } else {
+        Ok(vec!["--no-config".to_string()])
+    }
+}
+
+/// unstable, permission, import map, v8 and compat flags translated for the Deno version
+#[allow(deprecated)]
+fn deno_flags(artifact: &Artifact, import_map: &Option<String>, deno_version: Option<&str>) -> Vec<String> {
+    let mut flags = artifact.get_unstable_flags();
+    flags.extend(artifact.get_deno_permissions());
+    if let Some(import_map) = import_map {
+        flags.push(format!("--import-map={}", import_map));
+    }
+    if let Some(v8_flags) = &artifact.v8_flags {
+        flags.push(format!("--v8-flags={}", v8_flags));
+    }
+    if artifact.compat == Some(true) {
+        flags.push("--compat".to_string());
+    }
+    // flags requested by the catalog are dropped with a warning, and the script may behave differently
+    deno_compat::translate_flags(deno_version, flags, true)
+}
+
+/// cache script with `--reload` in `deno_dir`, and flags are unstable and lockfile flags.
+/// Output is captured, because scripts are cached concurrently
+pub fn cache(deno_bin_path: &str, script_name: &str, import_map: &Option<String>, flags: &[String], deno_dir: &Path) -> anyhow::Result<Output> {
+    dbang_utils::ensure_online(script_name)?;
     let mut command = Command::new(deno_bin_path);
     command.arg("cache")
         .arg("--no-check")
-        .arg("--unstable")
+        .args(flags)
         .arg("--reload")
         .arg("--quiet");
     if let Some(import_map) = import_map {
@@ -106,9 +201,40 @@ pub fn cache(deno_bin_path: &str, script_name: &str, import_map: &Option<String>
     let output = command
         .arg(script_name)
         .envs(std::env::vars())
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()?;
+        .env(deno_cache::DENO_DIR, deno_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(spawn_error)?;
     Ok(output)
 }
+
+/// error of starting Deno, and missing Deno binary is reported as not found
+fn spawn_error(error: std::io::Error) -> anyhow::Error {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        DbangError::not_found("Deno", "Please use `dbang deno add --default <version>` to install Deno").into()
+    } else {
+        error.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_local_path() {
+        let info = serde_json::json!({
+            "redirects": {"https://example.com/hello.ts": "https://example.com/v2/hello.ts"},
+            "modules": [
+                {"specifier": "https://example.com/dep.ts", "local": "/cache/dep"},
+                {"specifier": "https://example.com/v2/hello.ts", "local": "/cache/hello"}
+            ]
+        });
+        assert_eq!(module_local_path(&info, "https://example.com/hello.ts"), Some("/cache/hello"));
+        assert_eq!(module_local_path(&info, "https://example.com/dep.ts"), Some("/cache/dep"));
+        // no fallback to other modules
+        assert_eq!(module_local_path(&info, "https://example.com/other.ts"), None);
+    }
+}
@@ -0,0 +1,77 @@
+//! Deno flag translation between Deno releases
+use colored::*;
+use crate::deno_versions::parse_version;
+
+/// `--compat` removed from Deno 1.26.0
+pub const COMPAT_REMOVED_VERSION: (u32, u32, u32) = (1, 26, 0);
+/// `--unstable` and `--allow-hrtime` removed, workspace aware `deno.json` from Deno 2.0.0
+pub const DENO_2_VERSION: (u32, u32, u32) = (2, 0, 0);
+/// `--frozen` to refuse changes of lockfile from Deno 1.46.0
+pub const FROZEN_LOCKFILE_VERSION: (u32, u32, u32) = (1, 46, 0);
+
+/// flags removed from Deno: flag name, version removed from, hint for catalog authors
+const REMOVED_FLAGS: &[(&str, (u32, u32, u32), &str)] = &[
+    ("--compat", COMPAT_REMOVED_VERSION, "Node compatibility is built in, remove `compat`"),
+    ("--unstable", DENO_2_VERSION, "use a feature list, e.g. `\"unstable\": [\"kv\"]`"),
+    ("--allow-hrtime", DENO_2_VERSION, "high resolution time is always available, remove the permission"),
+    ("--lock-write", DENO_2_VERSION, "lockfile is written by default"),
+];
+
+fn is_at_least(deno_version: Option<&str>, target: (u32, u32, u32)) -> bool {
+    // unknown version means the latest Deno
+    deno_version.and_then(parse_version).map(|v| v >= target).unwrap_or(true)
+}
+
+/// find the removal of a flag for a Deno version, and return version removed from and hint
+pub fn removed_flag(deno_version: Option<&str>, flag: &str) -> Option<((u32, u32, u32), &'static str)> {
+    let flag_name = flag.split('=').next().unwrap_or(flag);
+    REMOVED_FLAGS.iter()
+        .find(|(name, removed_from, _)| *name == flag_name && is_at_least(deno_version, *removed_from))
+        .map(|(_, removed_from, hint)| (*removed_from, *hint))
+}
+
+/// translate flags for the Deno version and drop flags removed from it, with a warning for each dropped flag if `warn`
+pub fn translate_flags(deno_version: Option<&str>, flags: Vec<String>, warn: bool) -> Vec<String> {
+    flags.into_iter().filter(|flag| {
+        if let Some((_, hint)) = removed_flag(deno_version, flag) {
+            if warn {
+                eprintln!("{}", format!("Warning: {} dropped for Deno {}, {}", flag, deno_version.unwrap_or("latest"), hint).yellow());
+            }
+            false
+        } else {
+            true
+        }
+    }).collect()
+}
+
+/// Deno 2 treats `deno.json` as workspace config, and `--import-map` should not be mixed with it
+pub fn supports_config_with_import_map(deno_version: Option<&str>) -> bool {
+    !is_at_least(deno_version, DENO_2_VERSION)
+}
+
+/// flags to run with lockfile, and `--frozen` makes Deno refuse to update the lockfile
+pub fn lock_flags(deno_version: Option<&str>, lock_file: &str) -> Vec<String> {
+    let mut flags = vec![format!("--lock={}", lock_file)];
+    if is_at_least(deno_version, FROZEN_LOCKFILE_VERSION) {
+        flags.push("--frozen".to_string());
+    }
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_flags() {
+        let flags = vec!["--unstable".to_string(), "--compat".to_string(), "--allow-net".to_string()];
+        assert_eq!(translate_flags(Some("1.25.0"), flags.clone(), false), vec!["--unstable", "--compat", "--allow-net"]);
+        assert_eq!(translate_flags(Some("1.40.0"), flags.clone(), false), vec!["--unstable", "--allow-net"]);
+        assert_eq!(translate_flags(Some("2.0.0"), flags, false), vec!["--allow-net"]);
+        assert!(supports_config_with_import_map(Some("1.46.3")));
+        assert!(!supports_config_with_import_map(None));
+        assert_eq!(translate_flags(Some("2.1.0"), vec!["--lock=lock.json".to_string(), "--lock-write".to_string()], false), vec!["--lock=lock.json"]);
+        assert_eq!(lock_flags(Some("1.40.0"), "lock.json"), vec!["--lock=lock.json"]);
+        assert_eq!(lock_flags(Some("1.46.3"), "lock.json"), vec!["--lock=lock.json", "--frozen"]);
+    }
+}
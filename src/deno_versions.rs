@@ -3,96 +3,136 @@ use reqwest::blocking::Client;
 use std::path::{PathBuf};
 use std::fs;
 use std::io;
+use std::process::Command;
 use crate::dbang_utils;
+use crate::errors::DbangError;
 
-pub fn get_deno_binary(version: &str) -> PathBuf {
-    let deno_bin_path = get_deno_home(version).join("deno");
+/// oldest Deno version supported by dbang
+pub const MIN_SUPPORTED_VERSION: (u32, u32, u32) = (1, 25, 0);
+/// first Deno version with granular `--unstable-<feature>` flags
+pub const UNSTABLE_FEATURES_VERSION: (u32, u32, u32) = (1, 38, 0);
+
+pub fn get_deno_binary(version: &str) -> anyhow::Result<PathBuf> {
+    let deno_bin_path = get_deno_home(version)?.join("deno");
     if !deno_bin_path.exists() {
         println!("{} not found and begin to install Deno ...", version);
-        install(version).unwrap();
+        install(version)?;
     }
-    deno_bin_path
+    Ok(deno_bin_path)
 }
 
-pub fn get_default_deno() -> PathBuf {
-    dbang_utils::dbang_dir().join("bin/deno")
+pub fn get_default_deno() -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?.join("bin/deno"))
 }
 
 pub fn get_default_deno_version() -> Option<String> {
-    let deno_bin = get_default_deno();
+    let deno_bin = get_default_deno().ok()?;
     if deno_bin.exists() {
-        let deno_bin_path = fs::read_link(deno_bin).unwrap();
-        let deno_version = String::from(deno_bin_path.parent().unwrap().file_name().unwrap().to_string_lossy());
+        let deno_bin_path = fs::read_link(deno_bin).ok()?;
+        let deno_version = String::from(deno_bin_path.parent()?.file_name()?.to_string_lossy());
         return Some(deno_version);
     }
     None
 }
 
-pub fn get_deno_home(version: &str) -> PathBuf {
-    dbang_utils::dbang_dir()
+/// parse Deno version text, such as `1.38.0` or `v1.38.0`, into (major, minor, patch)
+pub fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().trim_start_matches('v').split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().unwrap_or("0").parse().ok()?;
+    let patch = parts.next().unwrap_or("0").split('-').next()?.parse().ok()?;
+    Some((major, minor, patch))
+}
+
+pub fn supports_unstable_features(version: &str) -> bool {
+    parse_version(version).map(|v| v >= UNSTABLE_FEATURES_VERSION).unwrap_or(false)
+}
+
+/// detect version of a Deno binary by `deno --version`
+pub fn detect_version(deno_bin: &str) -> Option<String> {
+    let output = Command::new(deno_bin).arg("--version").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .find(|line| line.starts_with("deno "))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .map(|version| version.to_string())
+}
+
+pub fn get_deno_home(version: &str) -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?
         .join("deno")
-        .join(version)
+        .join(version))
 }
 
 pub fn list() -> anyhow::Result<Vec<String>> {
-    let deno_dir = dbang_utils::dbang_dir().join("deno");
+    let deno_dir = dbang_utils::dbang_dir()?.join("deno");
+    if !deno_dir.exists() {
+        return Ok(Vec::new());
+    }
     let mut files = fs::read_dir(deno_dir)?;
     let mut versions = Vec::new();
     while let Some(file) = files.next() {
         let dir = file?;
         if dir.path().is_dir() && dir.path().join("deno").exists() {
             let file_name = dir.file_name();
-            let file_name = file_name.to_str().unwrap();
-            versions.push(file_name.to_string());
+            versions.push(file_name.to_string_lossy().to_string());
         }
     }
     Ok(versions)
 }
 
 pub fn install(version: &str) -> anyhow::Result<()> {
-    let deno_bin_path = get_deno_home(version).join("deno");
+    let deno_bin_path = get_deno_home(version)?.join("deno");
     if !deno_bin_path.exists() {
         download(version)?;
         unzip_deno(version)?;
-        fs::remove_file(get_deno_home(version).join("deno.zip"))?;
+        fs::remove_file(get_deno_home(version)?.join("deno.zip"))?;
     }
     Ok(())
 }
 
 pub fn link_as_default(version: &str) -> anyhow::Result<()> {
-    let target_deno_bin = get_deno_binary(version);
-    let default_deno_bin_link = dbang_utils::dbang_dir().join("bin/deno");
-    if default_deno_bin_link.exists() {
-        symlink::remove_symlink_file(&default_deno_bin_link).unwrap();
+    let target_deno_bin = get_deno_binary(version)?;
+    let default_deno_bin_link = dbang_utils::dbang_dir()?.join("bin/deno");
+    if default_deno_bin_link.symlink_metadata().is_ok() {
+        symlink::remove_symlink_file(&default_deno_bin_link)?;
     }
-    symlink::symlink_file(target_deno_bin, default_deno_bin_link).unwrap();
+    symlink::symlink_file(target_deno_bin, default_deno_bin_link)?;
     Ok(())
 }
 
 pub fn download(version: &str) -> anyhow::Result<()> {
-    let deno_version_dir = get_deno_home(version);
+    dbang_utils::ensure_online(&format!("Deno {}", version))?;
+    let deno_version_dir = get_deno_home(version)?;
     fs::create_dir_all(&deno_version_dir)?;
     let temp_zip_file = deno_version_dir.join("deno.zip");
-    let download_url = if cfg!(target_os = "macos") && cfg!(target_arch = "aarch64") {
-        format!("https://github.com/denoland/deno/releases/download/v{}/deno-aarch64-apple-darwin.zip", version)
+    let zip_name = if cfg!(target_os = "macos") && cfg!(target_arch = "aarch64") {
+        "deno-aarch64-apple-darwin.zip"
     } else if cfg!(target_os = "macos") {
-        format!("https://github.com/denoland/deno/releases/download/v{}/deno-x86_64-apple-darwin.zip", version)
+        "deno-x86_64-apple-darwin.zip"
     } else if cfg!(target_os = "windows") {
-        format!("https://github.com/denoland/deno/releases/download/v{}/deno-x86_64-pc-windows-msvc.zip", version)
+        "deno-x86_64-pc-windows-msvc.zip"
     } else {
-        format!("https://github.com/denoland/deno/releases/download/v{}/deno-x86_64-unknown-linux-gnu.zip", version)
+        "deno-x86_64-unknown-linux-gnu.zip"
     };
+    let download_url = format!("{}/v{}/{}", dbang_utils::deno_download_url(), version, zip_name);
     let mut response = Client::builder()
         .build()?
         .get(download_url)
-        .send()?;
+        .send()
+        .map_err(|e| DbangError::Network(format!("Unable to download Deno {}: {}", version, e.without_url())))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        fs::remove_dir_all(&deno_version_dir)?;
+        return Err(DbangError::not_found(format!("Deno {}", version),
+                                         "Please check versions at https://github.com/denoland/deno/releases").into());
+    }
     let mut zip_file = File::create(&temp_zip_file)?;
     io::copy(&mut response, &mut zip_file)?;
     Ok(())
 }
 
 pub fn delete(version: &str) -> anyhow::Result<()> {
-    let deno_version_dir = get_deno_home(version);
+    let deno_version_dir = get_deno_home(version)?;
     if deno_version_dir.exists() {
         fs::remove_dir_all(&deno_version_dir)?;
     }
@@ -100,7 +140,7 @@ pub fn delete(version: &str) -> anyhow::Result<()> {
 }
 
 pub fn unzip_deno(version: &str) -> anyhow::Result<()> {
-    let deno_version_dir = get_deno_home(version);
+    let deno_version_dir = get_deno_home(version)?;
     let deno_zip_file = deno_version_dir.join("deno.zip");
     //unzip zip_file to deno_version_dir
     let mut zip = zip::ZipArchive::new(File::open(deno_zip_file)?)?;
@@ -120,7 +160,7 @@ pub fn unzip_deno(version: &str) -> anyhow::Result<()> {
         #[cfg(any(unix, macos, linux))]
         {
             use std::os::unix::fs::PermissionsExt;
-            outfile.set_permissions(fs::Permissions::from_mode(file.unix_mode().unwrap())).unwrap();
+            outfile.set_permissions(fs::Permissions::from_mode(file.unix_mode().unwrap_or(0o755)))?;
         }
     }
     Ok(())
@@ -130,11 +170,6 @@ pub fn unzip_deno(version: &str) -> anyhow::Result<()> {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_deno_exists() {
-        println!("{}", get_deno_binary("1.25.0").exists());
-    }
-
     #[test]
     fn test_os_name() {
         let arch = std::env::consts::ARCH;
@@ -144,34 +179,10 @@ mod tests {
     }
 
     #[test]
-    fn test_deno_download() {
-        download("1.25.0").unwrap();
-    }
-
-    #[test]
-    fn test_unzip_deno() {
-        unzip_deno("1.25.0").unwrap();
-    }
-
-    #[test]
-    fn test_delete() {
-        delete("1.25.0").unwrap();
-    }
-
-    #[test]
-    fn test_install() {
-        install("1.25.0").unwrap();
-    }
-
-    #[test]
-    fn test_list() {
-        for x in list().unwrap() {
-            println!("{}", x);
-        }
-    }
-
-    #[test]
-    fn test_default_deno_version() {
-        println!("{:?}", get_default_deno_version());
+    fn test_parse_version() {
+        assert_eq!(parse_version("v1.38.0"), Some((1, 38, 0)));
+        assert_eq!(parse_version("2.0.0-rc.1"), Some((2, 0, 0)));
+        assert!(supports_unstable_features("1.40.2"));
+        assert!(!supports_unstable_features("1.37.2"));
     }
 }
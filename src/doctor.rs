@@ -0,0 +1,343 @@
+//! Health check of DBang environment by `dbang doctor`
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::de::DeserializeOwned;
+use crate::app_registry::{self, AppRegistry};
+use crate::catalog::{Artifact, Catalog};
+use crate::config::Config;
+use crate::dbang_utils;
+use crate::{deno_cache, deno_versions, runner};
+use crate::setup::{self, Shell};
+use crate::trust::TrustRules;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Pass => write!(f, "PASS"),
+            Status::Warn => write!(f, "WARN"),
+            Status::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// repair applied by `dbang doctor --fix`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fix {
+    /// append PATH lines to rc file of shell
+    AppendPathLines(Shell, Vec<String>),
+    /// link bin/deno to the Deno version, or remove it if no Deno installed
+    RelinkDefaultDeno(Option<String>),
+    /// rename corrupt file to `<file>.bak`
+    BackupFile(PathBuf),
+    /// fetch catalog from GitHub again, and accept it by trust rules or user
+    RefetchCatalog(String),
+    /// remove app from apps.json and its link
+    RemoveApp(String),
+    /// create link of app to dbang-shim again
+    RelinkApp(String),
+    /// delete Deno version dir without deno binary
+    DeleteDenoDir(PathBuf),
+}
+
+impl Fix {
+    pub fn apply(&self) -> anyhow::Result<String> {
+        match self {
+            Fix::AppendPathLines(shell, lines) => {
+                setup::append_path_lines(*shell, lines)?;
+                Ok(format!("PATH lines appended to {}", shell.rc_file()?.to_string_lossy()))
+            }
+            Fix::RelinkDefaultDeno(Some(version)) => {
+                deno_versions::link_as_default(version)?;
+                Ok(format!("bin/deno linked to Deno {}", version))
+            }
+            Fix::RelinkDefaultDeno(None) => {
+                symlink::remove_symlink_file(deno_versions::get_default_deno()?)?;
+                Ok("bin/deno removed".to_string())
+            }
+            Fix::BackupFile(file) => {
+                let mut backup_file = file.clone().into_os_string();
+                backup_file.push(".bak");
+                fs::rename(file, &backup_file)?;
+                Ok(format!("{} moved to {}", file.to_string_lossy(), backup_file.to_string_lossy()))
+            }
+            Fix::RefetchCatalog(repo_name) => {
+                if !runner::confirm_remote_catalog(repo_name)? {
+                    anyhow::bail!("Abort to accept dbang catalog {}", repo_name);
+                }
+                Ok(format!("{} catalog fetched again", repo_name))
+            }
+            Fix::RemoveApp(app_name) => {
+                if let Some(app_entry) = app_registry::get(app_name)? {
+                    let executable_path = app_entry.executable_path(app_name)?;
+                    if executable_path.symlink_metadata().is_ok() {
+                        fs::remove_file(executable_path)?;
+                    }
+                }
+                app_registry::remove(app_name)?;
+                Ok(format!("{} app removed", app_name))
+            }
+            Fix::RelinkApp(app_name) => {
+                let app_entry = app_registry::get(app_name)?
+                    .ok_or_else(|| anyhow::anyhow!("{} app not found", app_name))?;
+                let dbang_shim_path = dbang_utils::find_dbang_shim()
+                    .ok_or_else(|| anyhow::anyhow!("dbang-shim not found"))?;
+                let app_link = app_entry.executable_path(app_name)?;
+                if let Some(parent) = app_link.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                symlink::symlink_file(dbang_shim_path, &app_link)?;
+                Ok(format!("{} linked to dbang-shim", app_link.to_string_lossy()))
+            }
+            Fix::DeleteDenoDir(dir) => {
+                fs::remove_dir_all(dir)?;
+                Ok(format!("{} deleted", dir.to_string_lossy()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub status: Status,
+    pub message: String,
+    /// repair for `--fix`, None if it should be fixed manually
+    pub fix: Option<Fix>,
+}
+
+impl CheckResult {
+    fn pass(message: impl Into<String>) -> CheckResult {
+        CheckResult { status: Status::Pass, message: message.into(), fix: None }
+    }
+
+    fn warn(message: impl Into<String>, fix: Option<Fix>) -> CheckResult {
+        CheckResult { status: Status::Warn, message: message.into(), fix }
+    }
+
+    fn fail(message: impl Into<String>, fix: Option<Fix>) -> CheckResult {
+        CheckResult { status: Status::Fail, message: message.into(), fix }
+    }
+}
+
+/// run all checks, and network checks are skipped if `check_network` is false
+pub fn run_checks(check_network: bool) -> Vec<CheckResult> {
+    let dbang_dir = match dbang_utils::dbang_dir() {
+        Ok(dbang_dir) => dbang_dir,
+        Err(e) => return vec![CheckResult::fail(format!("{:#}", e), None)],
+    };
+    let mut results = Vec::new();
+    let registry = check_json_file::<AppRegistry>(dbang_dir.join("apps.json"), &mut results);
+    let config = check_json_file::<Config>(dbang_dir.join("config.json"), &mut results);
+    check_json_file::<TrustRules>(dbang_dir.join("trust.json"), &mut results);
+    results.extend(check_path(&dbang_dir, registry.as_ref(), config.as_ref()));
+    results.push(check_default_deno(&dbang_dir));
+    results.push(check_dbang_shim());
+    results.extend(check_catalogs());
+    if let Some(registry) = &registry {
+        results.extend(check_apps(registry));
+    }
+    results.extend(check_deno_cache(&dbang_dir));
+    if check_network {
+        // hosts required to fetch catalogs and scripts
+        results.extend([dbang_utils::github_raw_url(), dbang_utils::github_api_url()].iter().map(|url| check_url(url)));
+    }
+    results
+}
+
+/// parse JSON file, and report it if corrupt. None returned if file is missing or corrupt
+fn check_json_file<T: DeserializeOwned>(file: PathBuf, results: &mut Vec<CheckResult>) -> Option<T> {
+    let data = fs::read_to_string(&file).ok()?;
+    match serde_json::from_str(&data) {
+        Ok(value) => {
+            results.push(CheckResult::pass(format!("{} is valid", file.to_string_lossy())));
+            Some(value)
+        }
+        Err(e) => {
+            results.push(CheckResult::fail(format!("{} is corrupt: {}", file.to_string_lossy(), e), Some(Fix::BackupFile(file))));
+            None
+        }
+    }
+}
+
+fn check_path(dbang_dir: &Path, registry: Option<&AppRegistry>, config: Option<&Config>) -> Vec<CheckResult> {
+    // bin dirs with `~` are skipped without home dir
+    let mut candidate_dirs = vec![dbang_dir.join("bin")];
+    if let Some(config) = config {
+        candidate_dirs.extend(config.get_bin_dir().ok());
+    }
+    if let Some(registry) = registry {
+        candidate_dirs.extend(registry.apps.values().filter(|x| x.target.is_none()).filter_map(|x| x.get_bin_dir().ok()));
+    }
+    let mut bin_dirs: Vec<PathBuf> = Vec::new();
+    for dir in candidate_dirs {
+        if !bin_dirs.contains(&dir) {
+            bin_dirs.push(dir);
+        }
+    }
+    bin_dirs.into_iter().map(|dir| {
+        if setup::is_in_path(&dir) {
+            CheckResult::pass(format!("{} is in PATH", dir.to_string_lossy()))
+        } else {
+            let fix = Shell::detect().map(|shell| Fix::AppendPathLines(shell, vec![shell.path_line(&dir)]));
+            CheckResult::warn(format!("{} is not in PATH, please use `dbang setup` to add it", dir.to_string_lossy()), fix)
+        }
+    }).collect()
+}
+
+fn latest_installed_deno() -> Option<String> {
+    let mut versions = deno_versions::list().ok()?;
+    versions.sort_by_key(|version| deno_versions::parse_version(version));
+    versions.pop()
+}
+
+fn check_default_deno(dbang_dir: &Path) -> CheckResult {
+    let deno_bin = dbang_dir.join("bin/deno");
+    if deno_bin.symlink_metadata().is_err() {
+        return CheckResult::warn("No default Deno, please use `dbang deno add --default <version>`", None);
+    }
+    if fs::read_link(&deno_bin).is_err() {
+        return CheckResult::fail(format!("{} is not a symlink", deno_bin.to_string_lossy()),
+                                 Some(Fix::RelinkDefaultDeno(latest_installed_deno())));
+    }
+    if !deno_bin.exists() {
+        return CheckResult::fail(format!("{} is a dangling symlink", deno_bin.to_string_lossy()),
+                                 Some(Fix::RelinkDefaultDeno(latest_installed_deno())));
+    }
+    match deno_versions::get_default_deno_version() {
+        Some(version) => CheckResult::pass(format!("Default Deno is {}", version)),
+        None => CheckResult::warn(format!("Unable to detect Deno version of {}", deno_bin.to_string_lossy()), None),
+    }
+}
+
+fn check_dbang_shim() -> CheckResult {
+    match dbang_utils::find_dbang_shim() {
+        Some(shim_path) => CheckResult::pass(format!("dbang-shim found at {}", shim_path.to_string_lossy())),
+        None => CheckResult::fail("dbang-shim not found, please put it in the same dir as dbang or in PATH", None),
+    }
+}
+
+fn check_catalogs() -> Vec<CheckResult> {
+    Catalog::list_local().unwrap_or_default().into_iter().map(|repo_name| {
+        match Catalog::read_from_local(&repo_name) {
+            Ok(_) => CheckResult::pass(format!("{} catalog is valid", repo_name)),
+            Err(e) => CheckResult::fail(format!("{} catalog is corrupt: {:#}", repo_name, e), Some(Fix::RefetchCatalog(repo_name))),
+        }
+    }).collect()
+}
+
+fn check_apps(registry: &AppRegistry) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+    for (app_name, app_entry) in &registry.apps {
+        if let Some((script_name, repo_name)) = app_entry.script_and_repo() {
+            if !Catalog::local_exists(repo_name).unwrap_or(false) {
+                results.push(CheckResult::warn(format!("{} app points to deleted catalog {}", app_name, repo_name),
+                                               Some(Fix::RemoveApp(app_name.clone()))));
+                continue;
+            }
+            if Artifact::read_from_local(repo_name, script_name).is_err() {
+                results.push(CheckResult::warn(format!("{} app points to deleted script {}", app_name, app_entry.script),
+                                               Some(Fix::RemoveApp(app_name.clone()))));
+                continue;
+            }
+        }
+        let executable_path = match app_entry.executable_path(app_name) {
+            Ok(executable_path) => executable_path,
+            Err(e) => {
+                results.push(CheckResult::fail(format!("{} app: {:#}", app_name, e), None));
+                continue;
+            }
+        };
+        if executable_path.exists() {
+            results.push(CheckResult::pass(format!("{} app is installed at {}", app_name, executable_path.to_string_lossy())));
+        } else if app_entry.compiled {
+            results.push(CheckResult::warn(format!("{} is missing, please use `dbang apps upgrade {}` to compile it again",
+                                                   executable_path.to_string_lossy(), app_name), None));
+        } else if executable_path.symlink_metadata().is_ok() {
+            results.push(CheckResult::fail(format!("{} is a dangling symlink", executable_path.to_string_lossy()), None));
+        } else {
+            results.push(CheckResult::warn(format!("{} is missing", executable_path.to_string_lossy()),
+                                           Some(Fix::RelinkApp(app_name.clone()))));
+        }
+    }
+    results
+}
+
+fn check_deno_cache(dbang_dir: &Path) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+    if let Ok(entries) = fs::read_dir(dbang_dir.join("deno")) {
+        for entry in entries.flatten() {
+            let dir = entry.path();
+            if dir.is_dir() && !dir.join("deno").exists() {
+                results.push(CheckResult::warn(format!("{} has no deno binary, maybe an interrupted install", dir.to_string_lossy()),
+                                               Some(Fix::DeleteDenoDir(dir))));
+            }
+        }
+    }
+    // scripts are cached in Deno cache of each catalog
+    for catalog_full_name in Catalog::list_local().unwrap_or_default() {
+        let catalog = match Catalog::read_from_local(&catalog_full_name) {
+            Ok(catalog) => catalog,
+            Err(_) => continue, // reported by catalog checks
+        };
+        let deno_dir = match deno_cache::get_deno_dir(&catalog_full_name) {
+            Ok(deno_dir) => deno_dir,
+            Err(_) => continue,
+        };
+        if deno_dir.exists() && fs::metadata(&deno_dir).map(|x| x.permissions().readonly()).unwrap_or(true) {
+            results.push(CheckResult::fail(format!("Deno cache dir {} is not writable", deno_dir.to_string_lossy()), None));
+            continue;
+        }
+        let script_names: Vec<String> = catalog.get_artifacts().into_iter()
+            .filter(|(_, artifact)| artifact.is_platform_compatible())
+            .map(|(script_name, _)| script_name)
+            .collect();
+        let cached = script_names.iter().filter(|script_name| deno_cache::is_cached(&catalog_full_name, script_name)).count();
+        let summary = format!("Deno cache of {}: {} of {} scripts cached, {}", catalog_full_name, cached, script_names.len(),
+                              deno_cache::format_size(deno_cache::dir_size(&deno_dir)));
+        if cached < script_names.len() && catalog.lazy_cache != Some(true) {
+            results.push(CheckResult::warn(format!("{}, please use `dbang cache warm {}` to cache others", summary, catalog_full_name), None));
+        } else {
+            results.push(CheckResult::pass(summary));
+        }
+    }
+    results
+}
+
+fn check_url(url: &str) -> CheckResult {
+    let response = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .and_then(|client| client.head(url).send());
+    match response {
+        Ok(_) => CheckResult::pass(format!("{} is reachable", url)),
+        Err(e) => CheckResult::warn(format!("{} is not reachable: {}", url, e), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_json_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("config.json");
+        let mut results = Vec::new();
+        fs::write(&config_file, r#"{"bin-dir": "~/bin"}"#).unwrap();
+        assert!(check_json_file::<Config>(config_file.clone(), &mut results).is_some());
+        fs::write(&config_file, r#"{"bin-dir": "#).unwrap();
+        assert!(check_json_file::<Config>(config_file.clone(), &mut results).is_none());
+        assert_eq!(results[0].status, Status::Pass);
+        assert_eq!(results[1].status, Status::Fail);
+        assert_eq!(results[1].fix, Some(Fix::BackupFile(config_file)));
+    }
+}
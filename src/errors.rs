@@ -0,0 +1,138 @@
+//! Typed errors of DBang, with hints and exit codes
+use std::io;
+use std::path::Path;
+use colored::*;
+
+/// exit codes follow sysexits.h
+pub const EXIT_GENERAL: i32 = 1;
+pub const EXIT_PARSE: i32 = 65;
+pub const EXIT_NOT_FOUND: i32 = 66;
+pub const EXIT_NETWORK: i32 = 68;
+pub const EXIT_UNSUPPORTED_PLATFORM: i32 = 69;
+pub const EXIT_OFFLINE: i32 = 75;
+pub const EXIT_BAD_SIGNATURE: i32 = 76;
+pub const EXIT_PERMISSION_DENIED: i32 = 77;
+
+const NETWORK_HINT: &str = "Please check your network or proxy, and use `dbang doctor` to check reachability of GitHub";
+const PARSE_HINT: &str = "Please check it by `dbang catalog lint`, or use `dbang doctor --fix` to back up corrupt files";
+const PERMISSION_DENIED_HINT: &str = "Please check owner and permissions of the file";
+const NOT_FOUND_HINT: &str = "Please check the file exists, or use `dbang doctor` to check DBang environment";
+
+#[derive(Debug, thiserror::Error)]
+pub enum DbangError {
+    #[error("{what} not found")]
+    NotFound { what: String, hint: String },
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Unable to parse {file}: {reason}")]
+    Parse { file: String, reason: String },
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("{script} is not compatible with this platform, supported platforms: {platforms}")]
+    UnsupportedPlatform { script: String, platforms: String },
+    #[error("{0} is not cached, and network access is disabled in offline mode")]
+    Offline(String),
+    #[error("Signature of catalog {catalog} is invalid: {reason}")]
+    BadSignature { catalog: String, reason: String },
+    #[error("Catalog {catalog} is denied by trust rule {rule}")]
+    Denied { catalog: String, rule: String },
+}
+
+impl DbangError {
+    pub fn not_found(what: impl Into<String>, hint: impl Into<String>) -> DbangError {
+        DbangError::NotFound { what: what.into(), hint: hint.into() }
+    }
+
+    /// error of reading or writing a file, with kind of io error
+    pub fn from_io(path: &Path, error: io::Error) -> anyhow::Error {
+        let path_text = path.to_string_lossy().to_string();
+        match error.kind() {
+            io::ErrorKind::NotFound => DbangError::not_found(path_text, NOT_FOUND_HINT).into(),
+            io::ErrorKind::PermissionDenied => DbangError::PermissionDenied(path_text).into(),
+            _ => anyhow::Error::new(error).context(format!("Unable to access {}", path_text)),
+        }
+    }
+
+    pub fn hint(&self) -> String {
+        match self {
+            DbangError::NotFound { hint, .. } => hint.clone(),
+            DbangError::Network(_) => NETWORK_HINT.to_string(),
+            DbangError::Parse { .. } => PARSE_HINT.to_string(),
+            DbangError::PermissionDenied(_) => PERMISSION_DENIED_HINT.to_string(),
+            DbangError::UnsupportedPlatform { .. } => "Please run it on one of the supported platforms".to_string(),
+            DbangError::Offline(_) => "Please run it once without --offline or DBANG_OFFLINE to cache it".to_string(),
+            DbangError::BadSignature { .. } => "The catalog may be tampered, please contact its author or check the pinned key by `dbang trust list`".to_string(),
+            DbangError::Denied { rule, .. } => format!("Please remove the rule by `dbang trust delete {}` if you trust the catalog", rule),
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DbangError::NotFound { .. } => EXIT_NOT_FOUND,
+            DbangError::Network(_) => EXIT_NETWORK,
+            DbangError::Parse { .. } => EXIT_PARSE,
+            DbangError::PermissionDenied(_) => EXIT_PERMISSION_DENIED,
+            DbangError::UnsupportedPlatform { .. } => EXIT_UNSUPPORTED_PLATFORM,
+            DbangError::Offline(_) => EXIT_OFFLINE,
+            DbangError::BadSignature { .. } => EXIT_BAD_SIGNATURE,
+            DbangError::Denied { .. } => EXIT_PERMISSION_DENIED,
+        }
+    }
+}
+
+/// read file to string, and map missing or unreadable file to DbangError
+pub fn read_to_string(path: &Path) -> anyhow::Result<String> {
+    std::fs::read_to_string(path).map_err(|e| DbangError::from_io(path, e))
+}
+
+/// exit code and hint of error, from DbangError or well-known errors in the chain
+pub fn classify(error: &anyhow::Error) -> (i32, Option<String>) {
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<DbangError>() {
+            return (e.exit_code(), Some(e.hint()));
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            match e.kind() {
+                io::ErrorKind::NotFound => return (EXIT_NOT_FOUND, Some(NOT_FOUND_HINT.to_string())),
+                io::ErrorKind::PermissionDenied => return (EXIT_PERMISSION_DENIED, Some(PERMISSION_DENIED_HINT.to_string())),
+                _ => {}
+            }
+        }
+        if cause.is::<reqwest::Error>() {
+            return (EXIT_NETWORK, Some(NETWORK_HINT.to_string()));
+        }
+        if cause.is::<serde_json::Error>() {
+            return (EXIT_PARSE, Some(PARSE_HINT.to_string()));
+        }
+    }
+    (EXIT_GENERAL, None)
+}
+
+/// print error as one line with hint, and debug info with backtrace in verbose mode. Return exit code of the error
+pub fn report(error: &anyhow::Error, verbose: bool) -> i32 {
+    let (exit_code, hint) = classify(error);
+    eprintln!("{} {}", "error:".red().bold(), format!("{:#}", error).red());
+    if let Some(hint) = hint {
+        eprintln!("{} {}", "hint:".yellow(), hint);
+    }
+    if verbose {
+        eprintln!("{:?}", error);
+    }
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let error: anyhow::Error = DbangError::not_found("hello in catalog linux-china", "Please check script name").into();
+        assert_eq!(classify(&error.context("Failed to run hello")), (EXIT_NOT_FOUND, Some("Please check script name".to_string())));
+        let error = read_to_string(Path::new("/not-exist/dbang-catalog.json")).unwrap_err();
+        assert_eq!(classify(&error).0, EXIT_NOT_FOUND);
+        let error: anyhow::Error = serde_json::from_str::<serde_json::Value>("{").unwrap_err().into();
+        assert_eq!(classify(&error).0, EXIT_PARSE);
+        assert_eq!(classify(&anyhow::anyhow!("unknown")), (EXIT_GENERAL, None));
+    }
+}
@@ -0,0 +1,65 @@
+//! DBang library: resolve and run Deno scripts from catalogs on GitHub.
+//!
+//! Stable API, following semver of the dbang crate:
+//!
+//! * [`Catalog`], [`Artifact`] and [`Include`]: read, fetch and save `dbang-catalog.json`
+//! * [`deno_versions`]: install, list and link Deno versions in `~/.dbang/deno`
+//! * [`app_registry`]: installed apps in `~/.dbang/apps.json`
+//! * [`Runner`]: resolve and run scripts and apps
+//! * [`DbangError`]: typed errors with hints and exit codes
+//!
+//! ```no_run
+//! use dbang::{Catalog, Runner};
+//!
+//! let catalog = Catalog::read_from_local("linux-china")?;
+//! for (script_name, artifact) in catalog.search("hello") {
+//!     println!("{}: {}", script_name, artifact.script_ref);
+//! }
+//! let exit_code = Runner::new().non_interactive(true).run("hello@linux-china", &["world"])?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! [`Catalog`], [`Artifact`] and [`AppEntry`] are `#[non_exhaustive]`, and [`Runner::non_interactive`] refuses
+//! catalogs which need confirm instead of prompting on stdin.
+//!
+//! Other modules are shared with the `dbang` and `dbang-shim` binaries and may change in any release.
+
+pub mod app_registry;
+pub mod catalog;
+pub mod deno_versions;
+pub mod errors;
+pub mod runner;
+
+#[doc(hidden)]
+pub mod audit_log;
+#[doc(hidden)]
+pub mod catalog_include;
+#[doc(hidden)]
+pub mod catalog_init;
+#[doc(hidden)]
+pub mod catalog_lint;
+#[doc(hidden)]
+pub mod catalog_sign;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod dbang_utils;
+#[doc(hidden)]
+pub mod deno_cache;
+#[doc(hidden)]
+pub mod deno_cli;
+#[doc(hidden)]
+pub mod deno_compat;
+#[doc(hidden)]
+pub mod doctor;
+#[doc(hidden)]
+pub mod self_update;
+#[doc(hidden)]
+pub mod setup;
+#[doc(hidden)]
+pub mod trust;
+
+pub use app_registry::{AppEntry, AppRegistry};
+pub use catalog::{Artifact, Catalog, Include, Unstable};
+pub use errors::DbangError;
+pub use runner::Runner;
@@ -1,20 +1,17 @@
 mod app;
-mod deno_cli;
-mod deno_versions;
-mod catalog;
-mod known_catalogs;
-mod dbang_utils;
-mod aliases;
 
-use std::collections::HashMap;
-use std::io;
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::Duration;
 use colored_json::ToColoredJson;
-use which::which;
 use crate::app::build_app;
+use clap::ArgMatches;
 use colored::*;
-use crate::catalog::Catalog;
+use dbang::{app_registry, audit_log, catalog, catalog_init, catalog_lint, catalog_sign, config, dbang_utils, deno_cache, deno_versions,
+            doctor, errors, runner, self_update, setup, trust};
+use dbang::{Catalog, DbangError, Runner};
+use dbang::audit_log::{AuditEntry, AuditEvent};
+use dbang::trust::TrustLevel;
 use update_informer::{registry, Check};
 
 fn main() {
@@ -22,19 +19,15 @@ fn main() {
     let matches = app.get_matches();
     let verbose = matches.get_flag("verbose");
     let quiet = matches.get_flag("quiet");
-    if !quiet {
-        //update informer: dbang new version
-        let dbang_informer = update_informer::new(registry::GitHub, "dbangdev/dbang", app::VERSION);
-        if let Ok(Some(version)) = dbang_informer.check_version() {
-            println!("DBang new version available: {}", version);
-        }
-        //update informer: deno new version
-        if let Some(deno_version) = deno_versions::get_default_deno_version() {
-            let deno_informer = update_informer::new(registry::GitHub, "denoland/deno", &deno_version);
-            if let Ok(Some(version)) = deno_informer.check_version() {
-                println!("Deno new version available: {}, please use `dbang deno install --default {}` to update!", version, version);
-            }
-        }
+    if verbose && std::env::var_os("RUST_LIB_BACKTRACE").is_none() {
+        // capture backtraces of errors for verbose output
+        std::env::set_var("RUST_LIB_BACKTRACE", "1");
+    }
+    if matches.get_flag("offline") {
+        std::env::set_var(dbang_utils::DBANG_OFFLINE, "1");
+    }
+    if !quiet && !dbang_utils::is_offline() && is_interactive() {
+        check_updates();
     }
     // run artifact without 'run' sub command
     if matches.contains_id("script") {
@@ -44,20 +37,25 @@ fn main() {
             .flatten()
             .map(|s| s as &str)
             .collect::<Vec<_>>();
-        dbang_run(artifact_full_name, &artifact_args, verbose).unwrap();
-        return;
+        exit_with(Runner::new().verbose(verbose).run(artifact_full_name, &artifact_args), verbose);
     }
     if matches.subcommand().is_none() { //display help if no subcommand
         build_app().print_help().unwrap();
         return;
     }
+    // parse subcommand and run
+    let (sub_command, sub_command_args) = matches.subcommand().unwrap();
+    if let Err(e) = run_subcommand(sub_command, sub_command_args, verbose) {
+        std::process::exit(errors::report(&e, verbose));
+    }
+}
+
+fn run_subcommand(sub_command: &str, sub_command_args: &ArgMatches, verbose: bool) -> anyhow::Result<()> {
     // make sure DBANG_DIR ~/.dbang/bin exist
-    let dbang_bin_dir = dbang_utils::dbang_dir().join("bin");
+    let dbang_bin_dir = dbang_utils::dbang_bin_dir()?;
     if !dbang_bin_dir.exists() {
-        std::fs::create_dir_all(&dbang_bin_dir).unwrap();
+        std::fs::create_dir_all(&dbang_bin_dir)?;
     }
-    // parse subcommand and run
-    let (sub_command, sub_command_args) = matches.subcommand().unwrap();
     if sub_command == "run" {
         let artifact_args = sub_command_args.get_many::<String>("params")
             .into_iter()
@@ -65,12 +63,12 @@ fn main() {
             .map(|s| s as &str)
             .collect::<Vec<_>>();
         let artifact_full_name = sub_command_args.get_one::<String>("script").unwrap();
-        dbang_run(artifact_full_name, &artifact_args, verbose).unwrap();
+        exit_with(Runner::new().verbose(verbose).run(artifact_full_name, &artifact_args), verbose);
     }
     if sub_command == "open" {
         let artifact_full_name = sub_command_args.get_one::<String>("script").unwrap();
         let url = format!("https://github.com/{}", artifact_full_name);
-        dbang_utils::open_url(&url).unwrap();
+        dbang_utils::open_url(&url)?;
     } else if sub_command == "install" {
         let artifact_full_name = sub_command_args.get_one::<String>("script").unwrap();
         let app_name = if let Some(name) = sub_command_args.get_one::<String>("name") {
@@ -84,52 +82,151 @@ fn main() {
                 artifact_full_name.to_string()
             }
         };
-        if app_name == "dbang" || app_name.starts_with("dbang-") || app_name == "deno" {
-            println!("{}", "dbang, deno and dbang-* are reserved names, please use other names".red());
-            return;
+        if let Err(message) = app_registry::validate_app_name(&app_name) {
+            eprintln!("{}", message.red());
+            std::process::exit(1);
+        }
+        let mut app_entry = app_registry::AppEntry::new(artifact_full_name);
+        // bin dir from --bin-dir or config, and None for ~/.dbang/bin
+        app_entry.bin_dir = match sub_command_args.get_one::<String>("bin_dir") {
+            Some(bin_dir) => Some(std::path::absolute(dbang_utils::expand_home(bin_dir)?)?.to_string_lossy().to_string()),
+            None => config::load()?.bin_dir,
+        };
+        std::fs::create_dir_all(app_entry.get_bin_dir()?)?;
+        app_entry.compiled = sub_command_args.get_flag("compile");
+        app_entry.target = sub_command_args.get_one::<String>("target").cloned();
+        if !sub_command_args.get_flag("force") {
+            if let Some(reason) = app_registry::check_overwrite(&app_name, &app_entry)? {
+                eprintln!("{}", format!("{}, please use --force to replace it or --name to choose another name", reason).red());
+                std::process::exit(1);
+            }
+        }
+        if app_entry.target.is_none() {
+            for path in app_registry::find_path_collisions(&app_name, &app_entry.executable_path(&app_name)?) {
+                println!("{}", format!("Warning: {} collides with {} on PATH", app_name, path.to_string_lossy()).yellow());
+            }
+        }
+        let mut artifact = None;
+        if let Some((script_name, repo_name)) = artifact_full_name.split_once('@') {
+            artifact = if app_entry.compiled {
+                Some(runner::resolve_artifact(repo_name, script_name)?)
+            } else {
+                catalog::Artifact::read_from_local(repo_name, script_name).ok()
+            };
+            if let Some(artifact) = &artifact {
+                app_entry.resolve_with(artifact);
+            }
+            if let Some(commit) = catalog::resolve_head_commit(repo_name) {
+                app_entry.git_ref = commit;
+            }
+        }
+        if app_entry.compiled {
+            let artifact = match artifact {
+                Some(artifact) => artifact,
+                None => {
+                    eprintln!("{}", "Only script@repo can be compiled, e.g. hello@linux-china".red());
+                    std::process::exit(1);
+                }
+            };
+            let executable_path = runner::compile_app(&app_name, &app_entry, &artifact, verbose)?;
+            app_registry::add(&app_name, app_entry.clone())?;
+            audit_app(AuditEvent::Install, &app_name, &app_entry);
+            println!("{} app compiled to {}", app_name, executable_path.to_string_lossy());
+            return Ok(());
         }
-        aliases::add(app_name.clone(), artifact_full_name.to_string()).unwrap();
         //create soft link
-        let dbang_shim_path = which("dbang-shim").unwrap();
-        let app_link = dbang_bin_dir.join(&app_name);
-        if app_link.exists() {
-            symlink::remove_symlink_file(&app_link).unwrap();
+        let dbang_shim_path = dbang_utils::find_dbang_shim()
+            .ok_or_else(|| DbangError::not_found("dbang-shim", "Please check it by `dbang doctor`"))?;
+        let app_link = app_entry.executable_path(&app_name)?;
+        if app_link.symlink_metadata().is_ok() {
+            symlink::remove_symlink_file(&app_link)?;
+        }
+        symlink::symlink_file(dbang_shim_path, &app_link)?;
+        app_registry::add(&app_name, app_entry.clone())?;
+        // recorded after the app is installed, and failed installs are not logged
+        audit_app(AuditEvent::Install, &app_name, &app_entry);
+        println!("{} app installed to {}", app_name, app_link.to_string_lossy());
+        if !setup::is_in_path(app_link.parent().unwrap()) {
+            println!("{}", "Bin dir of app is not in PATH, please use `dbang setup` to add it".yellow());
         }
-        symlink::symlink_file(dbang_shim_path, app_link).unwrap();
-        println!("{} app installed", app_name);
     } else if sub_command == "uninstall" {
         let app_name = sub_command_args.get_one::<String>("name").unwrap();
-        aliases::remove(app_name).unwrap();
-        let app_link = dbang_bin_dir.join(&app_name);
-        if app_link.exists() {
-            symlink::remove_symlink_file(app_link).unwrap();
+        if let Err(message) = app_registry::validate_app_name(app_name) {
+            eprintln!("{}", message.red());
+            std::process::exit(1);
+        }
+        // only apps in apps.json are removed, and other files in bin dir are never touched
+        let app_entry = app_registry::get(app_name)?
+            .ok_or_else(|| DbangError::not_found(format!("{} app", app_name), "Please check installed apps by `dbang apps`"))?;
+        let executable_path = app_entry.executable_path(app_name)?;
+        if executable_path.symlink_metadata().is_ok() {
+            if app_entry.compiled {
+                std::fs::remove_file(&executable_path).map_err(|e| DbangError::from_io(&executable_path, e))?;
+            } else {
+                symlink::remove_symlink_file(&executable_path).map_err(|e| DbangError::from_io(&executable_path, e))?;
+            }
         }
+        app_registry::remove(app_name)?;
+        audit_app(AuditEvent::Uninstall, app_name, &app_entry);
         println!("{} uninstalled successfully", app_name);
     } else if sub_command == "apps" {
-        let apps: HashMap<String, String> = aliases::all().unwrap();
+        if let Some(("upgrade", upgrade_args)) = sub_command_args.subcommand() {
+            let apps = app_registry::all()?;
+            let app_names: Vec<String> = if upgrade_args.get_flag("all") {
+                apps.keys().cloned().collect()
+            } else if let Some(app_name) = upgrade_args.get_one::<String>("name") {
+                vec![app_name.to_string()]
+            } else {
+                eprintln!("{}", "Please specify app name or use --all".red());
+                std::process::exit(1);
+            };
+            for app_name in app_names {
+                if let Some(app_entry) = apps.get(&app_name) {
+                    if let Err(e) = upgrade_app(&app_name, app_entry.clone()) {
+                        eprintln!("{}", format!("Failed to upgrade {}: {}", app_name, e).red());
+                    }
+                } else {
+                    eprintln!("{}", format!("{} not installed", app_name).red());
+                }
+            }
+            return Ok(());
+        }
+        let apps = app_registry::all()?;
         if apps.is_empty() {
             println!("No apps installed");
         } else {
             println!("Local installed apps:");
-            for pair in apps {
-                println!("  {} -> {}", pair.0, pair.1);
+            for (app_name, app_entry) in apps {
+                if app_entry.compiled {
+                    println!("  {} -> {} (compiled)", app_name, app_entry.script);
+                } else {
+                    println!("  {} -> {}", app_name, app_entry.script);
+                }
+                if verbose {
+                    let commit: String = app_entry.git_ref.chars().take(7).collect();
+                    println!("      ref: {}, installed: {}, deno: {}", commit, app_entry.installed_at,
+                             app_entry.deno.as_deref().unwrap_or("default"));
+                    if !app_entry.permissions.is_empty() {
+                        println!("      permissions: {}", app_entry.permissions.join(" "));
+                    }
+                }
             }
         }
     } else if sub_command == "catalog" {
         if sub_command_args.subcommand().is_none() { // print help if no subcommand
-            build_app().find_subcommand("catalog").unwrap().clone().print_help().unwrap();
-            return;
+            build_app().find_subcommand("catalog").unwrap().clone().print_help()?;
+            return Ok(());
         }
         let (catalog_sub_command, catalog_sub_command_args) = sub_command_args.subcommand().unwrap();
         if catalog_sub_command == "list" {
             println!("Local installed catalogs:");
-            for catalog_full_name in catalog::Catalog::list_local().unwrap() {
+            for catalog_full_name in catalog::Catalog::list_local()? {
                 println!("  {}", catalog_full_name);
             };
         } else if catalog_sub_command == "add" || catalog_sub_command == "update" {
             let repo_name = catalog_sub_command_args.get_one::<String>("repo_name").unwrap();
             let repo_full_name = Catalog::get_full_repo_name(repo_name);
-            if confirm_remote_catalog(&repo_full_name).unwrap() {
+            if runner::confirm_remote_catalog(&repo_full_name)? {
                 if catalog_sub_command == "add" {
                     println!("Catalog added successfully!");
                 } else {
@@ -141,28 +238,354 @@ fn main() {
         } else if catalog_sub_command == "delete" {
             let repo_name = catalog_sub_command_args.get_one::<String>("repo_name").unwrap();
             let repo_full_name = Catalog::get_full_repo_name(repo_name);
-            catalog::Catalog::delete(&repo_full_name).unwrap();
-            known_catalogs::remove(&repo_full_name).unwrap();
-            aliases::remove_by_repo_name(&repo_full_name).unwrap();
+            catalog::Catalog::delete(&repo_full_name)?;
+            // deny rules are kept after the catalog deleted
+            let mut trust_rules = trust::load()?;
+            if trust_rules.find(&repo_full_name).is_some_and(|rule| rule.pattern == repo_full_name && rule.level != TrustLevel::Never) {
+                trust_rules.remove(&repo_full_name);
+                trust::save(&trust_rules)?;
+            }
+            app_registry::remove_by_repo_name(&repo_full_name)?;
             println!("Catalog deleted successfully!");
         } else if catalog_sub_command == "show" {
             let repo_name = catalog_sub_command_args.get_one::<String>("repo_name").unwrap();
-            let catalog = catalog::Catalog::read_from_local(repo_name).unwrap();
-            let catalog_json = serde_json::to_string(&catalog).unwrap();
-            println!("{}", catalog_json.to_colored_json_auto().unwrap());
+            let mut catalog = catalog::Catalog::read_from_local(repo_name)?;
+            if catalog_sub_command_args.get_flag("resolved") {
+                let repo_full_name = Catalog::get_full_repo_name(repo_name);
+                let scripts: Vec<(&String, &String, &catalog::Artifact)> = catalog.scripts.iter()
+                    .filter(|(script_name, _)| catalog_sub_command_args.get_one::<String>("script").is_none_or(|x| x == *script_name))
+                    .map(|(script_name, artifact)| (script_name, catalog.included_scripts.get(script_name).unwrap_or(&repo_full_name), artifact))
+                    .collect();
+                let name_width = scripts.iter().map(|(script_name, _, _)| script_name.len()).max().unwrap_or(0).max("SCRIPT".len());
+                let source_width = scripts.iter().map(|(_, source, _)| source.len()).max().unwrap_or(0).max("SOURCE".len());
+                println!("{:<name_width$}  {:<source_width$}  SCRIPT-REF", "SCRIPT", "SOURCE", name_width = name_width, source_width = source_width);
+                for (script_name, source, artifact) in scripts {
+                    println!("{:<name_width$}  {:<source_width$}  {}", script_name, source, artifact.script_ref, name_width = name_width, source_width = source_width);
+                }
+                return Ok(());
+            }
+            if catalog_sub_command_args.get_flag("effective") {
+                catalog = catalog.to_effective();
+            }
+            let catalog_json = match catalog_sub_command_args.get_one::<String>("script") {
+                Some(script_name) => {
+                    let artifact = catalog.scripts.get(script_name)
+                        .ok_or_else(|| DbangError::not_found(format!("{} in catalog {}", script_name, Catalog::get_full_repo_name(repo_name)),
+                                                             "Please check script names by `dbang catalog show <repo>`"))?;
+                    serde_json::to_string(artifact)?
+                }
+                None => serde_json::to_string(&catalog)?,
+            };
+            println!("{}", catalog_json.to_colored_json_auto()?);
+        } else if catalog_sub_command == "search" {
+            let keyword = catalog_sub_command_args.get_one::<String>("keyword").unwrap();
+            let mut repo_names = catalog::Catalog::list_local()?;
+            repo_names.sort();
+            for repo_name in repo_names {
+                let catalog = catalog::Catalog::read_from_local(&repo_name)?;
+                for (script_name, artifact) in catalog.search(keyword) {
+                    let description = artifact.description.clone().unwrap_or_default();
+                    println!("  {}@{}  {}", script_name, repo_name, description);
+                }
+            }
+        } else if catalog_sub_command == "relock" {
+            let repo_name = catalog_sub_command_args.get_one::<String>("repo_name").unwrap();
+            let repo_full_name = Catalog::get_full_repo_name(repo_name);
+            let report = catalog::Catalog::read_from_local(&repo_full_name)?.cache_artifacts(&repo_full_name, true)?;
+            for script_name in &report.cached {
+                println!("{}: {}", script_name, catalog::get_lock_file(&repo_full_name, script_name)?.to_string_lossy());
+            }
+            report.check(&repo_full_name)?;
+            println!("Lockfiles of {} refreshed!", repo_full_name);
+        } else if catalog_sub_command == "lint" {
+            let target = catalog_sub_command_args.get_one::<String>("target");
+            let (dbang_catalog_file, repo_name) = resolve_dbang_catalog_file(target.map(|x| x.as_str()))
+                .ok_or_else(|| DbangError::not_found("dbang-catalog.json", "Please specify a catalog file, directory or local catalog repo"))?;
+            let lint_options = catalog_lint::LintOptions {
+                repo_name,
+                check_urls: catalog_sub_command_args.get_flag("check_urls"),
+            };
+            let issues = catalog_lint::lint_file(&dbang_catalog_file, &lint_options)?;
+            for issue in &issues {
+                println!("{}:{}", dbang_catalog_file.to_string_lossy(), issue);
+            }
+            if issues.is_empty() {
+                println!("{}", "No problems found!".green());
+            } else if issues.iter().any(|issue| issue.severity == catalog_lint::Severity::Error) {
+                std::process::exit(1);
+            }
+        } else if catalog_sub_command == "sign" {
+            let catalog_path = catalog_sub_command_args.get_one::<String>("catalog");
+            let (dbang_catalog_file, local_repo) = resolve_dbang_catalog_file(catalog_path.map(|x| x.as_str()))
+                .ok_or_else(|| DbangError::not_found("dbang-catalog.json", "Please specify a catalog file or directory"))?;
+            // signature is bound to the file in the repo
+            let repo_full_name = catalog_sub_command_args.get_one::<String>("repo").map(|repo| Catalog::get_full_repo_name(repo))
+                .or(local_repo)
+                .ok_or_else(|| anyhow::anyhow!("Please specify GitHub repo of the catalog by --repo, e.g. --repo linux-china"))?;
+            let file_path = match catalog_sub_command_args.get_one::<String>("path") {
+                Some(path) => path.trim_start_matches("./").to_string(),
+                None => dbang_catalog_file.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            };
+            let secret_key_path = match catalog_sub_command_args.get_one::<String>("key") {
+                Some(key_path) => dbang_utils::expand_home(key_path)?,
+                None => catalog_sign::default_secret_key_path()?,
+            };
+            if catalog_sub_command_args.get_flag("generate_key") && !secret_key_path.exists() {
+                let public_key = catalog_sign::generate_key_pair(&secret_key_path)?;
+                println!("Key pair generated: {}, {}", secret_key_path.to_string_lossy(),
+                         catalog_sign::public_key_path(&secret_key_path).to_string_lossy());
+                println!("{}", "Please keep the secret key safe, and never commit it to the repo!".yellow());
+                println!("Public key {}", public_key.to_base64());
+            }
+            let (signature_file, public_key) = catalog_sign::sign_file(&dbang_catalog_file, &format!("{}/{}", repo_full_name, file_path), &secret_key_path)?;
+            println!("{} of {} signed by key {}", file_path, repo_full_name, public_key.key_id());
+            println!("Please commit {} with dbang-catalog.json, and users can trust the catalog by:", signature_file.to_string_lossy());
+            println!("  dbang trust add {} --key {}", repo_full_name, public_key.to_base64());
+        } else if catalog_sub_command == "add-script" {
+            let catalog_path = catalog_sub_command_args.get_one::<String>("catalog");
+            let (dbang_catalog_file, _) = resolve_dbang_catalog_file(catalog_path.map(|x| x.as_str()))
+                .ok_or_else(|| DbangError::not_found("dbang-catalog.json", "Please use `dbang init` to create it"))?;
+            let script_name = catalog_sub_command_args.get_one::<String>("name").unwrap();
+            let mut catalog = catalog::Catalog::read_raw_from_file(&dbang_catalog_file)?;
+            if catalog.scripts.contains_key(script_name) && !catalog_sub_command_args.get_flag("force") {
+                eprintln!("{}", format!("{} already in dbang-catalog.json, please use --force to replace it!", script_name).red());
+                std::process::exit(1);
+            }
+            let script_ref = catalog_sub_command_args.get_one::<String>("script_ref").unwrap();
+            let mut artifact = catalog::Artifact::new(script_ref);
+            artifact.description = catalog_sub_command_args.get_one::<String>("description").cloned();
+            artifact.deno = catalog_sub_command_args.get_one::<String>("deno").cloned();
+            artifact.permissions = catalog_sub_command_args.get_many::<String>("permissions")
+                .map(|permissions| permissions.cloned().collect());
+            artifact.args = catalog_sub_command_args.get_many::<String>("args")
+                .map(|args| args.cloned().collect());
+            catalog.scripts.insert(script_name.to_string(), artifact);
+            catalog.write_to_file(&dbang_catalog_file)?;
+            println!("{} added to {}", script_name, dbang_catalog_file.to_string_lossy());
         } else {
             println!("{}", "Unknown subcommand");
         }
+    } else if sub_command == "config" {
+        let mut dbang_config = config::load()?;
+        match sub_command_args.subcommand() {
+            Some(("set", set_args)) => {
+                let key = set_args.get_one::<String>("key").unwrap();
+                let value = set_args.get_one::<String>("value").unwrap();
+                if let Err(e) = dbang_config.set(key, Some(value.to_string())) {
+                    eprintln!("{}", e.to_string().red());
+                    std::process::exit(1);
+                }
+                config::save(&dbang_config)?;
+                println!("{} = {}", key, value);
+            }
+            Some(("unset", unset_args)) => {
+                let key = unset_args.get_one::<String>("key").unwrap();
+                if let Err(e) = dbang_config.set(key, None) {
+                    eprintln!("{}", e.to_string().red());
+                    std::process::exit(1);
+                }
+                config::save(&dbang_config)?;
+                println!("{} removed", key);
+            }
+            _ => {
+                for key in config::CONFIG_KEYS {
+                    println!("{} = {}", key, dbang_config.get(key).unwrap_or_default());
+                }
+            }
+        }
+    } else if sub_command == "setup" {
+        let shell = match sub_command_args.get_one::<String>("shell") {
+            Some(shell_name) => setup::Shell::from_name(shell_name),
+            None => setup::Shell::detect(),
+        };
+        let shell = match shell {
+            Some(shell) => shell,
+            None => {
+                eprintln!("{}", "Unable to detect shell, please use --shell bash|zsh|fish".red());
+                std::process::exit(1);
+            }
+        };
+        // default bin dir, configured bin dir and bin dirs of installed apps
+        let mut candidate_dirs = vec![dbang_bin_dir.clone(), config::load()?.get_bin_dir()?];
+        for app_entry in app_registry::all()?.values().filter(|x| x.target.is_none()) {
+            candidate_dirs.push(app_entry.get_bin_dir()?);
+        }
+        let mut bin_dirs: Vec<PathBuf> = Vec::new();
+        for dir in candidate_dirs {
+            if !bin_dirs.contains(&dir) && !setup::is_in_path(&dir) {
+                bin_dirs.push(dir);
+            }
+        }
+        if bin_dirs.is_empty() {
+            println!("{}", "Bin dirs of DBang are in PATH already!".green());
+            return Ok(());
+        }
+        let path_lines: Vec<String> = bin_dirs.iter().map(|dir| shell.path_line(dir)).collect();
+        if sub_command_args.get_flag("append") {
+            let appended_lines = setup::append_path_lines(shell, &path_lines)?;
+            if appended_lines.is_empty() {
+                println!("PATH already configured in {}", shell.rc_file()?.to_string_lossy());
+            } else {
+                println!("Following lines appended to {}, please restart your shell:", shell.rc_file()?.to_string_lossy());
+                for line in appended_lines {
+                    println!("  {}", line);
+                }
+            }
+        } else {
+            println!("Please add following lines to {}, or use `dbang setup --append`:", shell.rc_file()?.to_string_lossy());
+            for line in path_lines {
+                println!("  {}", line);
+            }
+        }
+    } else if sub_command == "cache" {
+        if sub_command_args.subcommand().is_none() { // print help if no subcommand
+            build_app().find_subcommand("cache").unwrap().clone().print_help()?;
+            return Ok(());
+        }
+        let (cache_sub_command, cache_sub_command_args) = sub_command_args.subcommand().unwrap();
+        // catalog of the command, or all local catalogs
+        let repo_names = match cache_sub_command_args.try_get_one::<String>("repo_name").ok().flatten() {
+            Some(repo_name) => vec![Catalog::get_full_repo_name(repo_name)],
+            None => {
+                let mut repo_names = Catalog::list_local()?;
+                repo_names.sort();
+                repo_names
+            }
+        };
+        if cache_sub_command == "list" {
+            println!("Deno caches of catalogs:");
+            for repo_name in repo_names {
+                let deno_dir = deno_cache::get_deno_dir(&repo_name)?;
+                if deno_dir.exists() {
+                    let cached_count = deno_cache::CacheState::load(&repo_name)?.scripts.len();
+                    let script_count = Catalog::read_from_local(&repo_name).map(|catalog| catalog.scripts.len()).unwrap_or_default();
+                    println!("  {}  {}, {}/{} scripts cached", repo_name, deno_cache::format_size(deno_cache::dir_size(&deno_dir)),
+                             cached_count, script_count);
+                } else {
+                    println!("  {}  not cached", repo_name);
+                }
+            }
+        } else if cache_sub_command == "size" {
+            let size: u64 = repo_names.iter().map(|repo_name| deno_cache::get_deno_dir(repo_name).map(|deno_dir| deno_cache::dir_size(&deno_dir)))
+                .sum::<anyhow::Result<u64>>()?;
+            match cache_sub_command_args.get_one::<String>("repo_name") {
+                Some(_) => println!("{}: {}", repo_names[0], deno_cache::format_size(size)),
+                None => println!("Total: {}", deno_cache::format_size(size)),
+            }
+        } else if cache_sub_command == "clean" {
+            let mut freed_size = 0;
+            for repo_name in &repo_names {
+                freed_size += deno_cache::clean(repo_name)?;
+            }
+            println!("{} freed, please use `dbang cache warm <repo>` to cache scripts again", deno_cache::format_size(freed_size));
+        } else if cache_sub_command == "warm" {
+            let target = cache_sub_command_args.get_one::<String>("target").unwrap();
+            if let Some((script_name, repo_name)) = target.split_once('@') {
+                let repo_full_name = Catalog::get_full_repo_name(repo_name);
+                let artifact = runner::resolve_artifact(&repo_full_name, script_name)?;
+                let fingerprint = deno_cache::fingerprint(&artifact, &repo_full_name);
+                if deno_cache::CacheState::load(&repo_full_name)?.is_changed(script_name, fingerprint.as_deref()) {
+                    artifact.cache(&repo_full_name, script_name)?;
+                    println!("{} cached", target);
+                } else {
+                    println!("{} is up to date", target);
+                }
+            } else {
+                let repo_full_name = Catalog::get_full_repo_name(target);
+                let report = Catalog::read_from_local(&repo_full_name)?.cache_artifacts(&repo_full_name, false)?;
+                report.check(&repo_full_name)?;
+                if report.cached.is_empty() {
+                    println!("Scripts of {} are up to date", repo_full_name);
+                } else {
+                    println!("{} cached: {}", repo_full_name, report.cached.join(", "));
+                }
+            }
+        } else {
+            println!("Unknown subcommand");
+        }
+    } else if sub_command == "log" {
+        let filter = audit_log::AuditFilter {
+            event: sub_command_args.get_one::<String>("event").and_then(|name| AuditEvent::from_name(name)),
+            catalog: sub_command_args.get_one::<String>("catalog").cloned(),
+            script: sub_command_args.get_one::<String>("script").cloned(),
+            since: sub_command_args.get_one::<String>("since").map(|since| parse_since(since)).transpose()?,
+        };
+        let mut entries = audit_log::read(&filter)?;
+        if let Some(limit) = sub_command_args.get_one::<usize>("limit") {
+            entries.drain(..entries.len().saturating_sub(*limit));
+        }
+        for entry in entries {
+            if sub_command_args.get_flag("json") {
+                println!("{}", serde_json::to_string(&entry)?);
+            } else {
+                println!("{}", entry);
+            }
+        }
+    } else if sub_command == "doctor" {
+        let fix = sub_command_args.get_flag("fix");
+        let mut failed = false;
+        for result in doctor::run_checks(!dbang_utils::is_offline()) {
+            let label = format!("[{}]", result.status);
+            let label = match result.status {
+                doctor::Status::Pass => label.green(),
+                doctor::Status::Warn => label.yellow(),
+                doctor::Status::Fail => label.red(),
+            };
+            println!("{} {}", label, result.message);
+            let mut fixed = false;
+            if let Some(repair) = &result.fix {
+                if fix {
+                    match repair.apply() {
+                        Ok(message) => {
+                            println!("       {} {}", "fixed:".green(), message);
+                            fixed = true;
+                        }
+                        Err(e) => println!("       {} {}", "fix failed:".red(), e),
+                    }
+                } else {
+                    println!("       fixable by `dbang doctor --fix`");
+                }
+            }
+            if result.status == doctor::Status::Fail && !fixed {
+                failed = true;
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+    } else if sub_command == "self-update" {
+        if sub_command_args.get_flag("rollback") {
+            self_update::rollback()?;
+            println!("DBang rolled back to the previous version");
+        } else {
+            match self_update::update(app::VERSION, sub_command_args.get_flag("force"))? {
+                Some(version) => println!("DBang updated to {}", version),
+                None => println!("DBang {} is up to date", app::VERSION),
+            }
+        }
+    } else if sub_command == "init" {
+        let dir = sub_command_args.get_one::<String>("dir")
+            .map(PathBuf::from)
+            .map_or_else(std::env::current_dir, Ok)?;
+        let changed_files = catalog_init::init(&dir)?;
+        if changed_files.is_empty() {
+            println!("Catalog already initialized in {}", dir.to_string_lossy());
+        } else {
+            for file in changed_files {
+                println!("  {}", file.to_string_lossy());
+            }
+            println!("Catalog initialized, please use `dbang catalog add-script` to add more scripts!");
+        }
     } else if sub_command == "deno" {
         if sub_command_args.subcommand().is_none() { // print help if no subcommand
-            build_app().find_subcommand("deno").unwrap().clone().print_help().unwrap();
-            return;
+            build_app().find_subcommand("deno").unwrap().clone().print_help()?;
+            return Ok(());
         }
         let (deno_sub_command, deno_sub_command_args) = sub_command_args.subcommand().unwrap();
         if deno_sub_command == "list" {
             println!("Local Deno versions:");
             let default_deno_version = deno_versions::get_default_deno_version().unwrap_or("".to_owned());
-            for deno_version in deno_versions::list().unwrap() {
+            for deno_version in deno_versions::list()? {
                 if default_deno_version == deno_version {
                     println!("* {}", deno_version);
                 } else {
@@ -176,139 +599,235 @@ fn main() {
                 deno_version = deno_version[1..].to_string();
             }
             println!("Begin to install Deno {} ...", deno_version);
-            deno_versions::install(&deno_version).unwrap();
+            deno_versions::install(&deno_version)?;
             println!("Deno {} installed successfully!", deno_version);
             if as_default {
-                deno_versions::link_as_default(&deno_version).unwrap();
+                deno_versions::link_as_default(&deno_version)?;
                 println!("Default deno switched to {}", deno_version);
             }
         } else if deno_sub_command == "delete" {
             let deno_version = deno_sub_command_args.get_one::<String>("version").unwrap();
-            deno_versions::delete(deno_version).unwrap();
+            deno_versions::delete(deno_version)?;
             println!("Deno deleted successfully!");
         } else if deno_sub_command == "default" {
             let deno_version = deno_sub_command_args.get_one::<String>("version").unwrap();
-            deno_versions::link_as_default(deno_version).unwrap();
+            deno_versions::link_as_default(deno_version)?;
             println!("Default deno switched to {}", deno_version);
         } else {
             println!("{}", "Unknown subcommand");
         }
     } else if sub_command == "trust" {
         if sub_command_args.subcommand().is_none() { // print help if no subcommand
-            build_app().find_subcommand("trust").unwrap().clone().print_help().unwrap();
-            return;
+            build_app().find_subcommand("trust").unwrap().clone().print_help()?;
+            return Ok(());
         }
         let (trust_sub_command, trust_sub_command_args) = sub_command_args.subcommand().unwrap();
         if trust_sub_command == "list" {
-            println!("Local trusted catalogs:");
-            for catalog in known_catalogs::list().unwrap() {
-                println!("  {}", catalog);
-            };
+            let trust_rules = trust::load()?;
+            if let Some(repo_name) = trust_sub_command_args.get_one::<String>("repo_name") {
+                println!("{}", describe_trust(&trust_rules, &Catalog::get_full_repo_name(repo_name)));
+                return Ok(());
+            }
+            println!("Trust rules:");
+            for rule in &trust_rules.rules {
+                match rule.public_key()? {
+                    Some(public_key) => println!("  {}  {} (signed by key {})", rule.pattern, rule.level, public_key.key_id()),
+                    None => println!("  {}  {}", rule.pattern, rule.level),
+                }
+            }
+            println!("Local catalogs:");
+            for catalog_full_name in catalog::Catalog::list_local()? {
+                println!("  {}", describe_trust(&trust_rules, &catalog_full_name));
+            }
         } else if trust_sub_command == "add" {
-            let repo_name = trust_sub_command_args.get_one::<String>("repo_name").unwrap().to_string();
-            known_catalogs::add(&Catalog::get_full_repo_name(&repo_name)).unwrap();
-            println!("Catalog in trusted list now!");
+            let repo_name = trust_sub_command_args.get_one::<String>("repo_name").unwrap();
+            let level_name = trust_sub_command_args.get_one::<String>("level").unwrap();
+            let mut rule = trust::TrustRule::new(repo_name, TrustLevel::from_name(level_name).unwrap());
+            let public_key = match trust_sub_command_args.get_one::<String>("key") {
+                Some(key) => Some(catalog_sign::PublicKey::from_arg(key)?),
+                None => None,
+            };
+            rule.key = public_key.as_ref().map(|key| key.to_base64());
+            let mut trust_rules = trust::load()?;
+            // pinned key of the existing rule is kept, and it's removed by `dbang trust delete` only
+            let rule = trust_rules.add(rule).clone();
+            let saved_key = rule.public_key()?;
+            trust::save(&trust_rules)?;
+            // recorded after the rule is saved, and failed changes are not logged
+            let mut detail = format!("level {}", rule.level);
+            if let Some(saved_key) = &saved_key {
+                detail.push_str(&format!(", key {}", saved_key.key_id()));
+            }
+            AuditEntry::new(AuditEvent::TrustAdd).catalog(&rule.pattern).detail(detail).record();
+            println!("Trust rule added: {}  {}", rule.pattern, rule.level);
+            match (public_key, saved_key) {
+                (Some(public_key), _) => println!("Catalogs must be signed by key {}", public_key.key_id()),
+                (None, Some(saved_key)) => println!("Pinned key {} is kept, and catalogs must be signed by it", saved_key.key_id()),
+                (None, None) => {}
+            }
         } else if trust_sub_command == "delete" {
-            let repo_name = trust_sub_command_args.get_one::<String>("repo_name").unwrap().to_string();
-            known_catalogs::remove(&Catalog::get_full_repo_name(&repo_name)).unwrap();
-            println!("Catalog removed from trusted list!");
+            let repo_name = trust_sub_command_args.get_one::<String>("repo_name").unwrap();
+            let mut trust_rules = trust::load()?;
+            match trust_rules.remove(repo_name) {
+                Some(rule) => {
+                    trust::save(&trust_rules)?;
+                    AuditEntry::new(AuditEvent::TrustDelete).catalog(&rule.pattern).detail(format!("level {}", rule.level)).record();
+                    println!("Trust rule removed: {}", rule.pattern);
+                }
+                None => println!("{}", format!("No trust rule for {}", trust::normalize_pattern(repo_name)).yellow()),
+            }
         } else {
             println!("{}", "Unknown subcommand");
         }
     }
+    Ok(())
 }
 
-fn confirm_remote_catalog(repo_name: &str) -> anyhow::Result<bool> {
-    // check trusted catalog or not
-    let is_trusted = known_catalogs::include(repo_name)?;
-    if is_trusted {
-        catalog::save_remote_dbang_catalog(repo_name)?;
-        return Ok(true);
+/// time of `--since`, a date such as `2024-01-31` or a duration ago such as `7d`
+fn parse_since(since: &str) -> anyhow::Result<chrono::DateTime<chrono::FixedOffset>> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        let time = date.and_hms_opt(0, 0, 0).unwrap().and_local_timezone(chrono::Local).single()
+            .ok_or_else(|| anyhow::anyhow!("Invalid date {}", since))?;
+        return Ok(time.fixed_offset());
     }
-    let catalog = catalog::Catalog::fetch_from_github(repo_name)?;
-    let catalog_json = serde_json::to_string(&catalog)?;
-    println!("Detail of dbang-catalog.json:");
-    println!("{}", catalog_json.to_colored_json_auto()?);
-    print!("Do you accept above catalog?  y/n > ");
-    io::stdout().flush()?;
-    let mut buffer = String::new();
-    io::stdin().read_line(&mut buffer)?;
-    return if buffer.trim().starts_with("y") {
-        catalog.save(repo_name)?;
-        catalog.cache_artifacts(repo_name)?;
-        Ok(true)
-    } else {
-        Ok(false)
-    };
+    let interval = config::parse_interval(since)?;
+    Ok((chrono::Local::now() - chrono::Duration::from_std(interval)?).fixed_offset())
+}
+
+/// record install or uninstall of app in audit log
+fn audit_app(event: AuditEvent, app_name: &str, app_entry: &app_registry::AppEntry) {
+    let mut entry = AuditEntry::new(event).script(app_name).detail(format!("{} at ref {}", app_entry.script, app_entry.git_ref));
+    entry.catalog = app_entry.catalog.clone();
+    entry.permissions = app_entry.permissions.clone();
+    entry.deno = app_entry.deno.clone();
+    entry.record();
 }
 
-fn dbang_run(artifact_full_name: &str, artifact_args: &[&str], verbose: bool) -> anyhow::Result<()> {
-    if !artifact_full_name.contains('@') { // run from local dbang-catalog.json
-        return dbang_run_script(artifact_full_name, artifact_args, verbose);
+/// trust level of catalog with the matched rule
+fn describe_trust(trust_rules: &trust::TrustRules, catalog_full_name: &str) -> String {
+    match trust_rules.find(catalog_full_name) {
+        Some(rule) => format!("{}  {} by rule {}", catalog_full_name, rule.level, rule.pattern),
+        None => format!("{}  {}, no rule matched", catalog_full_name, TrustLevel::Prompt),
     }
-    let artifact_parts: Vec<&str> = artifact_full_name.split("@").collect();
-    let repo_name = artifact_parts[1];
-    let script_name = artifact_parts[0];
-    // validate local catalog exists or not
-    if !Catalog::local_exists(repo_name)? {
-        if !confirm_remote_catalog(repo_name)? {
-            println!("Abort to accept dbang catalog!");
+}
+
+/// re-fetch catalog of app, show changes of script, then save catalog and refresh Deno cache
+fn upgrade_app(app_name: &str, mut app_entry: app_registry::AppEntry) -> anyhow::Result<()> {
+    let (script_name, repo_name) = match app_entry.script_and_repo() {
+        Some((script_name, repo_name)) => (script_name.to_string(), Catalog::get_full_repo_name(repo_name)),
+        None => {
+            println!("{}: local script {}, skipped", app_name, app_entry.script);
             return Ok(());
         }
-    }
-    let artifact = catalog::Artifact::read_from_local(repo_name, script_name).unwrap();
-    if !artifact.is_platform_compatible() {
-        eprintln!("Script is not compatible with this platform: {:?}", artifact.platforms.as_ref().unwrap());
-        return Ok(());
-    }
-    let script_url = artifact.get_script_http_url(repo_name);
-    let permissions: Vec<String> = artifact.get_deno_permissions();
-    if verbose {
-        println!("[dbang] begin to run {}/{}", script_name, artifact_full_name);
-        println!("[dbang] script url:  {}", script_url);
-        if let Some(ref description) = artifact.description {
-            println!("[dbang] script description:  {}", description);
+    };
+    trust::ensure_not_denied(&repo_name)?;
+    let catalog = catalog::Catalog::fetch_from_github(&repo_name)?;
+    let artifact = catalog.get_artifact(&script_name)
+        .ok_or_else(|| anyhow::anyhow!("{} removed from catalog {}", script_name, repo_name))?;
+    let (changes, permissions_changed) = match catalog::Artifact::read_from_local(&repo_name, &script_name) {
+        Ok(local_artifact) => (local_artifact.diff(&artifact), local_artifact.get_deno_permissions() != artifact.get_deno_permissions()),
+        Err(_) => (vec![format!("{} is new in local catalog", script_name)], true),
+    };
+    if changes.is_empty() {
+        println!("{}: {} is up to date", app_name, app_entry.script);
+    } else {
+        println!("{}: changes of {}", app_name, app_entry.script);
+        for change in &changes {
+            println!("  {}", change);
         }
-        if !permissions.is_empty() {
-            println!("[dbang] script permissions:  {}", permissions.join(","));
+        // the whole catalog is saved, so permission changes of other scripts need confirm too
+        let catalog_changes = catalog.local_permission_changes(&repo_name)?;
+        if !catalog_changes.is_empty() {
+            println!("Permission changes of catalog {}:", repo_name);
+            for change in &catalog_changes {
+                println!("  {}", change);
+            }
+        }
+        if !catalog.included_catalogs.is_empty() {
+            println!("Catalogs included by {}: {}", repo_name, catalog.included_catalogs.join(", "));
         }
+        let trust_level = trust::level_with_includes(&repo_name, &catalog.included_catalogs)?;
+        let requires_confirm = trust_level.requires_confirm(permissions_changed || !catalog_changes.is_empty());
+        if requires_confirm && !dbang_utils::confirm("Do you accept above changes?")? {
+            AuditEntry::new(AuditEvent::CatalogReject).catalog(&repo_name).detail(format!("upgrade of {} rejected by user", app_name)).record();
+            println!("{}", format!("{} upgrade aborted", app_name).red());
+            return Ok(());
+        }
+        catalog.save(&repo_name)?;
+        let detail = if requires_confirm { "accepted by user".to_string() } else { format!("trust level {}", trust_level) };
+        AuditEntry::new(AuditEvent::CatalogAccept).catalog(&repo_name).detail(detail).record();
+        runner::record_included_catalogs(&repo_name, &catalog);
+    }
+    artifact.cache(&repo_name, &script_name)?;
+    let commit = catalog::resolve_head_commit(&repo_name);
+    app_entry.resolve_with(&artifact);
+    if app_entry.compiled {
+        let executable_path = runner::compile_app(app_name, &app_entry, &artifact, false)?;
+        println!("{}: compiled to {}", app_name, executable_path.to_string_lossy());
     }
-    deno_cli::run(repo_name, &artifact, artifact_args, verbose)?;
+    if let Some(commit) = commit {
+        app_entry.git_ref = commit;
+    }
+    app_entry.upgraded_at = Some(chrono::Local::now().to_rfc3339());
+    app_registry::add(app_name, app_entry)?;
+    println!("{} upgraded successfully", app_name);
     Ok(())
 }
 
-fn dbang_run_script(artifact_full_name: &str, artifact_args: &[&str], verbose: bool) -> anyhow::Result<()> {
-    let current_dir = std::env::current_dir()?;
-    let dbang_catalog_file = find_local_dbang_catalog(Some(current_dir.as_path()));
-    if dbang_catalog_file.is_none() {
-        eprintln!("dbang-catalog.json not found in current directory, parent directories or $HOME/.dbang");
-        return Ok(());
-    } else {
-        let dbang_catalog_json_file = dbang_catalog_file.unwrap();
-        let catalog = catalog::Catalog::read_from_file(&dbang_catalog_json_file)?;
-        if let Some(artifact) = catalog.scripts.get(artifact_full_name) {
-            deno_cli::run_local(dbang_catalog_json_file.parent().unwrap(), artifact, artifact_args, verbose)?;
-            Ok(())
-        } else {
-            println!("{}", format!("{} is not in dbang-catalog.json!", artifact_full_name).red());
-            Ok(())
+/// resolve dbang-catalog.json from file path, directory or local catalog repo name,
+/// and return the file with repo full name for a local catalog repo
+fn resolve_dbang_catalog_file(target: Option<&str>) -> Option<(PathBuf, Option<String>)> {
+    if let Some(target) = target {
+        let path = PathBuf::from(target);
+        if path.is_file() {
+            return Some((path, None));
+        } else if path.is_dir() {
+            let dbang_catalog_file = path.join("dbang-catalog.json");
+            return if dbang_catalog_file.exists() { Some((dbang_catalog_file, None)) } else { None };
         }
+        let repo_full_name = Catalog::get_full_repo_name(target);
+        let dbang_catalog_file = dbang_utils::dbang_dir().ok()?
+            .join("catalogs/github")
+            .join(&repo_full_name)
+            .join("dbang-catalog.json");
+        return if dbang_catalog_file.exists() { Some((dbang_catalog_file, Some(repo_full_name))) } else { None };
     }
+    let current_dir = std::env::current_dir().ok()?;
+    runner::find_local_dbang_catalog(Some(current_dir.as_path())).map(|file| (file, None))
 }
 
-fn find_local_dbang_catalog(base_dir: Option<&Path>) -> Option<PathBuf> {
-    if let Some(dir) = base_dir {
-        let dbang_catalog_file = dir.join("dbang-catalog.json");
-        return if dbang_catalog_file.exists() {
-            Some(dbang_catalog_file)
-        } else {
-            find_local_dbang_catalog(dir.parent())
-        };
-    }
-    let default_dbang_catalog = dirs::home_dir().unwrap().join(".dbang").join("dbang-catalog.json");
-    return if default_dbang_catalog.exists() {
-        Some(default_dbang_catalog)
-    } else {
-        None
+/// stderr is a terminal and not in CI
+fn is_interactive() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("CI").is_none()
+}
+
+/// check new versions of DBang and Deno, at most once per `update-check-interval` in config
+fn check_updates() {
+    let interval = match config::load().ok().and_then(|dbang_config| dbang_config.get_update_check_interval()) {
+        Some(interval) => interval,
+        None => return,
     };
+    let timeout = Duration::from_secs(3);
+    let dbang_informer = update_informer::new(registry::GitHub, "dbangdev/dbang", app::VERSION)
+        .interval(interval)
+        .timeout(timeout);
+    if let Ok(Some(version)) = dbang_informer.check_version() {
+        eprintln!("DBang new version available: {}, please use `dbang self-update` to update!", version);
+    }
+    if let Some(deno_version) = deno_versions::get_default_deno_version() {
+        let deno_informer = update_informer::new(registry::GitHub, "denoland/deno", &deno_version)
+            .interval(interval)
+            .timeout(timeout);
+        if let Ok(Some(version)) = deno_informer.check_version() {
+            eprintln!("Deno new version available: {}, please use `dbang deno add --default {}` to update!", version, version);
+        }
+    }
+}
+
+/// exit with exit code of script, or print error and exit with 1
+fn exit_with(result: anyhow::Result<i32>, verbose: bool) -> ! {
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(e) => std::process::exit(errors::report(&e, verbose)),
+    }
 }
@@ -0,0 +1,284 @@
+//! Run scripts from catalogs, shared by dbang and dbang-shim
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail};
+use colored::*;
+use colored_json::ToColoredJson;
+use crate::app_registry::AppEntry;
+use crate::audit_log::{AuditEntry, AuditEvent};
+use crate::catalog::{Artifact, Catalog};
+use crate::errors::DbangError;
+use crate::{app_registry, catalog, dbang_utils, deno_cache, deno_cli, trust};
+use crate::trust::TrustLevel;
+
+/// Runner of scripts in catalogs and installed apps
+///
+/// ```no_run
+/// let exit_code = dbang::Runner::new().verbose(true).non_interactive(true).run("hello@linux-china", &["world"])?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Runner {
+    verbose: bool,
+    non_interactive: bool,
+}
+
+impl Runner {
+    pub fn new() -> Runner {
+        Runner::default()
+    }
+
+    /// print debug info and Deno command line
+    pub fn verbose(mut self, verbose: bool) -> Runner {
+        self.verbose = verbose;
+        self
+    }
+
+    /// never prompt on stdin or print to stdout: catalogs and permission changes which need confirm by trust rules
+    /// are refused with [`DbangError::PermissionDenied`], for embedders without a terminal
+    pub fn non_interactive(mut self, non_interactive: bool) -> Runner {
+        self.non_interactive = non_interactive;
+        self
+    }
+
+    /// resolve `script@repo` to artifact, and fetch the catalog if it's missing
+    pub fn resolve(&self, artifact_full_name: &str) -> anyhow::Result<Artifact> {
+        let (script_name, repo_name) = artifact_full_name.split_once('@')
+            .ok_or_else(|| anyhow!("Invalid script name {}, format should be script@repo", artifact_full_name))?;
+        self.resolve_artifact(repo_name, script_name)
+    }
+
+    /// run `script@repo`, or script in local dbang-catalog.json, and return exit code of Deno
+    pub fn run(&self, artifact_full_name: &str, artifact_args: &[&str]) -> anyhow::Result<i32> {
+        let (script_name, repo_name) = match artifact_full_name.split_once('@') {
+            Some(parts) => parts,
+            None => return run_script(artifact_full_name, artifact_args, self.verbose), // run from local dbang-catalog.json
+        };
+        let artifact = self.resolve_artifact(repo_name, script_name)?;
+        run_artifact(repo_name, script_name, &artifact, artifact_args, self.verbose)
+    }
+
+    /// run app installed in apps.json, and return exit code of Deno
+    pub fn run_app(&self, app_name: &str, artifact_args: &[&str]) -> anyhow::Result<i32> {
+        let app_entry = app_registry::get(app_name)?
+            .ok_or_else(|| DbangError::not_found(format!("{} app", app_name),
+                                                 format!("Please install it by `dbang install <script> --name {}`", app_name)))?;
+        if self.verbose {
+            eprintln!("[dbang] app: {} -> {}", app_name, app_entry.script);
+            eprintln!("[dbang] dbang dir: {}", dbang_utils::dbang_dir()?.to_string_lossy());
+        }
+        self.run_app_entry(app_name, &app_entry, artifact_args)
+    }
+
+    /// read artifact from local catalog, and fetch the catalog if it's missing
+    fn resolve_artifact(&self, repo_name: &str, script_name: &str) -> anyhow::Result<Artifact> {
+        trust::ensure_not_denied(&Catalog::get_full_repo_name(repo_name))?;
+        if !Catalog::local_exists(repo_name)? && !self.accept_remote_catalog(repo_name)? {
+            bail!("Abort to accept dbang catalog {}", Catalog::get_full_repo_name(repo_name));
+        }
+        Artifact::read_from_local(repo_name, script_name)
+    }
+
+    /// run installed app, and prompt to confirm if permissions changed after install
+    fn run_app_entry(&self, app_name: &str, app_entry: &AppEntry, artifact_args: &[&str]) -> anyhow::Result<i32> {
+        let (script_name, repo_name) = match app_entry.script_and_repo() {
+            Some(parts) => parts,
+            None => return run_script(&app_entry.script, artifact_args, self.verbose),
+        };
+        let artifact = self.resolve_artifact(repo_name, script_name)?;
+        let permissions = artifact.get_deno_permissions();
+        if permissions != app_entry.permissions {
+            eprintln!("{}", format!("Permissions of {} changed:", app_name).yellow());
+            eprintln!("  granted:   {}", app_entry.permissions.join(" "));
+            eprintln!("  requested: {}", permissions.join(" "));
+            let trust_level = trust::level(&Catalog::get_full_repo_name(repo_name))?;
+            if trust_level.requires_confirm(true) {
+                if self.non_interactive {
+                    return Err(confirm_required(format!("permission changes of {}", app_name)));
+                }
+                if !dbang_utils::confirm("Do you grant above permissions?")? {
+                    bail!("Permissions not granted for {}", app_name);
+                }
+            }
+            let mut app_entry = app_entry.clone();
+            app_entry.resolve_with(&artifact);
+            app_registry::add(app_name, app_entry)?;
+        }
+        run_artifact(repo_name, script_name, &artifact, artifact_args, self.verbose)
+    }
+
+    /// fetch remote catalog, and save it if trusted or accepted by user
+    fn accept_remote_catalog(&self, repo_name: &str) -> anyhow::Result<bool> {
+        let catalog_full_name = Catalog::get_full_repo_name(repo_name);
+        if let Err(e) = trust::ensure_not_denied(&catalog_full_name) {
+            AuditEntry::new(AuditEvent::CatalogReject).catalog(&catalog_full_name).detail(e.to_string()).record();
+            return Err(e);
+        }
+        let catalog = Catalog::fetch_from_github(&catalog_full_name)?;
+        let trust_level = trust::level_with_includes(&catalog_full_name, &catalog.included_catalogs)?;
+        let permission_changes = catalog.local_permission_changes(&catalog_full_name)?;
+        if !trust_level.requires_confirm(!permission_changes.is_empty()) {
+            catalog.save(&catalog_full_name)?;
+            AuditEntry::new(AuditEvent::CatalogAccept).catalog(&catalog_full_name).detail(format!("trust level {}", trust_level)).record();
+            record_included_catalogs(&catalog_full_name, &catalog);
+            return Ok(true);
+        }
+        if self.non_interactive {
+            AuditEntry::new(AuditEvent::CatalogReject).catalog(&catalog_full_name).detail("rejected in non-interactive mode").record();
+            return Err(confirm_required(format!("catalog {} with trust level {}", catalog_full_name, trust_level)));
+        }
+        if !catalog.included_catalogs.is_empty() {
+            println!("Catalogs included by {}: {}", catalog_full_name, catalog.included_catalogs.join(", "));
+        }
+        if trust_level == TrustLevel::PromptOnChange {
+            println!("Permission changes of {}:", catalog_full_name);
+            for change in &permission_changes {
+                println!("  {}", change);
+            }
+        } else {
+            let catalog_json = serde_json::to_string(&catalog)?;
+            println!("Detail of dbang-catalog.json:");
+            println!("{}", catalog_json.to_colored_json_auto()?);
+        }
+        if dbang_utils::confirm("Do you accept above catalog?")? {
+            catalog.save(&catalog_full_name)?;
+            AuditEntry::new(AuditEvent::CatalogAccept).catalog(&catalog_full_name).detail("accepted by user").record();
+            record_included_catalogs(&catalog_full_name, &catalog);
+            if catalog.lazy_cache != Some(true) {
+                catalog.cache_artifacts(&catalog_full_name, false)?.check(&catalog_full_name)?;
+            }
+            Ok(true)
+        } else {
+            AuditEntry::new(AuditEvent::CatalogReject).catalog(&catalog_full_name).detail("rejected by user").record();
+            Ok(false)
+        }
+    }
+}
+
+/// refused in non-interactive mode, because trust rules require confirm
+fn confirm_required(what: String) -> anyhow::Error {
+    DbangError::PermissionDenied(format!("{} requires confirm, but the runner is non-interactive", what)).into()
+}
+
+/// fetch remote catalog, and save it if trusted or accepted by user
+pub fn confirm_remote_catalog(repo_name: &str) -> anyhow::Result<bool> {
+    Runner::new().accept_remote_catalog(repo_name)
+}
+
+/// record catalogs included by accepted catalog in audit log
+pub fn record_included_catalogs(catalog_full_name: &str, catalog: &Catalog) {
+    for included_catalog in &catalog.included_catalogs {
+        AuditEntry::new(AuditEvent::CatalogAccept).catalog(included_catalog).detail(format!("included by {}", catalog_full_name)).record();
+    }
+}
+
+/// read artifact from local catalog, and fetch the catalog if it's missing
+pub fn resolve_artifact(repo_name: &str, script_name: &str) -> anyhow::Result<Artifact> {
+    Runner::new().resolve_artifact(repo_name, script_name)
+}
+
+/// run `script@repo`, or script in local dbang-catalog.json, and return exit code of Deno
+pub fn run(artifact_full_name: &str, artifact_args: &[&str], verbose: bool) -> anyhow::Result<i32> {
+    Runner::new().verbose(verbose).run(artifact_full_name, artifact_args)
+}
+
+/// run installed app, and prompt to confirm if permissions changed after install
+pub fn run_app(app_name: &str, app_entry: &AppEntry, artifact_args: &[&str], verbose: bool) -> anyhow::Result<i32> {
+    Runner::new().verbose(verbose).run_app_entry(app_name, app_entry, artifact_args)
+}
+
+fn run_artifact(repo_name: &str, script_name: &str, artifact: &Artifact, artifact_args: &[&str], verbose: bool) -> anyhow::Result<i32> {
+    if !artifact.is_platform_compatible() {
+        return Err(unsupported_platform(script_name, artifact));
+    }
+    let script_url = artifact.get_script_http_url(repo_name);
+    let permissions: Vec<String> = artifact.get_deno_permissions();
+    if verbose {
+        eprintln!("[dbang] begin to run {}@{}", script_name, repo_name);
+        eprintln!("[dbang] script url:  {}", script_url);
+        if let Some(ref description) = artifact.description {
+            eprintln!("[dbang] script description:  {}", description);
+        }
+        if !permissions.is_empty() {
+            eprintln!("[dbang] script permissions:  {}", permissions.join(","));
+        }
+    }
+    ensure_cached(repo_name, script_name, artifact)?;
+    let mut output = deno_cli::run(repo_name, script_name, artifact, artifact_args, verbose)?;
+    // `--cached-only` failed before running the script, e.g. files removed from DENO_DIR, then cache it and retry once
+    if !output.status.success() && !dbang_utils::is_offline() && deno_cache::is_module_cached(repo_name, &script_url) == Some(false) {
+        eprintln!("[dbang] {}@{} missing in Deno cache, caching it and running again ...", script_name, repo_name);
+        artifact.cache(repo_name, script_name)?;
+        output = deno_cli::run(repo_name, script_name, artifact, artifact_args, verbose)?;
+    }
+    Ok(output.status.code().unwrap_or(1))
+}
+
+/// cache script before `deno run --cached-only` if it's not in Deno cache of the catalog: scripts of lazy catalogs,
+/// scripts added after the catalog cached, caches cleaned, and catalogs cached in global Deno cache by old DBang
+fn ensure_cached(repo_name: &str, script_name: &str, artifact: &Artifact) -> anyhow::Result<()> {
+    if deno_cache::is_cached(repo_name, script_name) {
+        return Ok(());
+    }
+    eprintln!("[dbang] {}@{} not in Deno cache, caching it ...", script_name, repo_name);
+    artifact.cache(repo_name, script_name)
+}
+
+/// run script in dbang-catalog.json of current directory, parent directories or $HOME/.dbang
+pub fn run_script(artifact_full_name: &str, artifact_args: &[&str], verbose: bool) -> anyhow::Result<i32> {
+    let current_dir = std::env::current_dir()?;
+    let dbang_catalog_json_file = find_local_dbang_catalog(Some(current_dir.as_path()))
+        .ok_or_else(|| DbangError::not_found("dbang-catalog.json in current directory, parent directories or $HOME/.dbang",
+                                             "Please use script@repo to run a script from GitHub, or `dbang init` to create a catalog"))?;
+    let catalog = catalog::Catalog::read_from_file(&dbang_catalog_json_file)?;
+    let artifact = catalog.get_artifact(artifact_full_name)
+        .ok_or_else(|| DbangError::not_found(format!("{} in {}", artifact_full_name, dbang_catalog_json_file.to_string_lossy()),
+                                             "Please check script names in dbang-catalog.json"))?;
+    if !artifact.is_platform_compatible() {
+        return Err(unsupported_platform(artifact_full_name, &artifact));
+    }
+    let catalog_dir = dbang_catalog_json_file.parent().unwrap_or(Path::new("."));
+    let output = deno_cli::run_local(catalog_dir, artifact_full_name, &artifact, artifact_args, verbose)?;
+    Ok(output.status.code().unwrap_or(1))
+}
+
+/// compile app to a standalone executable, and return path of the executable
+pub fn compile_app(app_name: &str, app_entry: &AppEntry, artifact: &Artifact, verbose: bool) -> anyhow::Result<PathBuf> {
+    let (_, repo_name) = app_entry.script_and_repo()
+        .ok_or_else(|| anyhow!("Only script@repo can be compiled: {}", app_entry.script))?;
+    if app_entry.target.is_none() && !artifact.is_platform_compatible() {
+        return Err(unsupported_platform(&app_entry.script, artifact));
+    }
+    let executable_path = app_entry.executable_path(app_name)?;
+    if let Some(parent) = executable_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let output = deno_cli::compile(repo_name, artifact, &executable_path, app_entry.target.as_deref(), verbose)?;
+    if !output.status.success() {
+        bail!("Failed to compile {} by `deno compile`", app_entry.script);
+    }
+    Ok(executable_path)
+}
+
+fn unsupported_platform(script_name: &str, artifact: &Artifact) -> anyhow::Error {
+    DbangError::UnsupportedPlatform {
+        script: script_name.to_string(),
+        platforms: artifact.platforms.clone().unwrap_or_default().join(", "),
+    }.into()
+}
+
+pub fn find_local_dbang_catalog(base_dir: Option<&Path>) -> Option<PathBuf> {
+    if let Some(dir) = base_dir {
+        let dbang_catalog_file = dir.join("dbang-catalog.json");
+        return if dbang_catalog_file.exists() {
+            Some(dbang_catalog_file)
+        } else {
+            find_local_dbang_catalog(dir.parent())
+        };
+    }
+    let default_dbang_catalog = dbang_utils::dbang_dir().ok()?.join("dbang-catalog.json");
+    if default_dbang_catalog.exists() {
+        Some(default_dbang_catalog)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,362 @@
+//! Update dbang and dbang-shim from GitHub releases
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use crate::dbang_utils;
+use crate::deno_versions;
+use crate::errors::DbangError;
+
+/// binaries in release asset, replaced together
+const BINARY_NAMES: &[&str] = &["dbang", "dbang-shim"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallMethod {
+    /// binaries from GitHub releases, updated by `dbang self-update`
+    Standalone,
+    Cargo,
+    Homebrew,
+    /// system package manager or Nix
+    PackageManager,
+}
+
+impl InstallMethod {
+    /// detect install method by path of dbang executable
+    pub fn detect(exe_path: &Path) -> InstallMethod {
+        let path_text = exe_path.to_string_lossy().replace('\\', "/");
+        let cargo_bin_dir = std::env::var("CARGO_HOME").map(PathBuf::from).ok()
+            .or_else(|| dbang_utils::expand_home("~/.cargo").ok())
+            .map(|cargo_home| cargo_home.join("bin"));
+        if cargo_bin_dir.is_some_and(|cargo_bin_dir| exe_path.starts_with(cargo_bin_dir)) {
+            InstallMethod::Cargo
+        } else if path_text.contains("/Cellar/") || path_text.contains("/homebrew/") || path_text.contains("/linuxbrew/") {
+            InstallMethod::Homebrew
+        } else if path_text.starts_with("/usr/bin/") || path_text.starts_with("/nix/store/") || path_text.starts_with("/snap/") {
+            InstallMethod::PackageManager
+        } else {
+            InstallMethod::Standalone
+        }
+    }
+
+    /// command to update dbang installed by other tools
+    pub fn update_command(&self) -> Option<&'static str> {
+        match self {
+            InstallMethod::Standalone => None,
+            InstallMethod::Cargo => Some("cargo install dbang"),
+            InstallMethod::Homebrew => Some("brew upgrade dbang"),
+            InstallMethod::PackageManager => Some("the package manager which installed dbang"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Release {
+    tag_name: String,
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// target triple of current platform, same as names of release assets
+pub fn host_triple() -> String {
+    let os = match std::env::consts::OS {
+        "macos" => "apple-darwin",
+        "windows" => "pc-windows-msvc",
+        _ => "unknown-linux-gnu",
+    };
+    format!("{}-{}", std::env::consts::ARCH, os)
+}
+
+fn asset_name(version: &str, triple: &str) -> String {
+    format!("dbang-{}-{}.tar", version, triple)
+}
+
+/// file name of binary on current platform
+fn binary_file_name(name: &str) -> String {
+    if cfg!(target_os = "windows") { format!("{}.exe", name) } else { name.to_string() }
+}
+
+/// old binary kept for rollback, such as `dbang.old`
+fn backup_path(binary_path: &Path) -> PathBuf {
+    let mut file_name = binary_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".old");
+    binary_path.with_file_name(file_name)
+}
+
+/// bin dir of dbang and dbang-shim to replace, and dbang-shim is in the same dir as dbang
+fn installed_bin_dir() -> anyhow::Result<PathBuf> {
+    let exe_path = std::env::current_exe()?.canonicalize()?;
+    let bin_dir = exe_path.parent().ok_or_else(|| anyhow!("Unable to find dir of {}", exe_path.to_string_lossy()))?;
+    Ok(bin_dir.to_path_buf())
+}
+
+fn http_client() -> anyhow::Result<Client> {
+    Ok(Client::builder().user_agent("dbang").build()?)
+}
+
+fn fetch_latest_release() -> anyhow::Result<Release> {
+    dbang_utils::ensure_online("Latest release of DBang")?;
+    let url = format!("{}/repos/dbangdev/dbang/releases/latest", dbang_utils::github_api_url());
+    let response = http_client()?.get(url).send()
+        .map_err(|e| DbangError::Network(format!("Unable to fetch latest release of DBang: {}", e.without_url())))?;
+    if !response.status().is_success() {
+        return Err(DbangError::Network(format!("Unable to fetch latest release of DBang: HTTP {}", response.status())).into());
+    }
+    Ok(response.json()?)
+}
+
+fn download(url: &str) -> anyhow::Result<Vec<u8>> {
+    let response = http_client()?.get(url).send()
+        .map_err(|e| DbangError::Network(format!("Unable to download {}: {}", url, e.without_url())))?;
+    if !response.status().is_success() {
+        return Err(DbangError::Network(format!("Unable to download {}: HTTP {}", url, response.status())).into());
+    }
+    Ok(response.bytes()?.to_vec())
+}
+
+/// verify SHA-256 of data with checksum file, format is `<hex digest>  <file name>`
+pub fn verify_checksum(data: &[u8], checksum_text: &str) -> anyhow::Result<()> {
+    let expected = checksum_text.split_whitespace().next()
+        .ok_or_else(|| anyhow!("Empty checksum file"))?
+        .to_lowercase();
+    let actual = format!("{:x}", Sha256::digest(data));
+    if actual != expected {
+        bail!("Checksum mismatch, expected {} but got {}", expected, actual);
+    }
+    Ok(())
+}
+
+/// extract binaries from tar of release, and entries are `dbang-<version>/bin/<binary>`
+fn extract_binaries(tar_data: &[u8]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = tar::Archive::new(tar_data);
+    let mut binaries = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let file_name = entry.path()?.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+        if BINARY_NAMES.iter().any(|name| binary_file_name(name) == file_name) {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            binaries.push((file_name, data));
+        }
+    }
+    for name in BINARY_NAMES {
+        if !binaries.iter().any(|(file_name, _)| *file_name == binary_file_name(name)) {
+            bail!("{} not found in release asset", name);
+        }
+    }
+    Ok(binaries)
+}
+
+/// write new binaries next to old ones, then move old binaries to `.old` and new ones into place.
+/// Binaries are replaced together: on any error, replaced binaries are restored and staged files removed
+fn replace_binaries(bin_dir: &Path, binaries: &[(String, Vec<u8>)]) -> anyhow::Result<()> {
+    let mut staged = Vec::new();
+    let result = stage_binaries(bin_dir, binaries, &mut staged).and_then(|()| swap_binaries(&staged));
+    if result.is_err() {
+        for (new_path, _) in &staged {
+            let _ = fs::remove_file(new_path);
+        }
+    }
+    result
+}
+
+fn stage_binaries(bin_dir: &Path, binaries: &[(String, Vec<u8>)], staged: &mut Vec<(PathBuf, PathBuf)>) -> anyhow::Result<()> {
+    for (file_name, data) in binaries {
+        let new_path = bin_dir.join(format!(".{}.new", file_name));
+        staged.push((new_path.clone(), bin_dir.join(file_name)));
+        fs::write(&new_path, data).map_err(|e| DbangError::from_io(&new_path, e))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&new_path, fs::Permissions::from_mode(0o755))?;
+        }
+    }
+    Ok(())
+}
+
+fn swap_binaries(staged: &[(PathBuf, PathBuf)]) -> anyhow::Result<()> {
+    // replaced binaries, and whether the old binary was moved to `.old`
+    let mut swapped: Vec<(&Path, bool)> = Vec::new();
+    for (new_path, binary_path) in staged {
+        match swap_binary(new_path, binary_path) {
+            Ok(backed_up) => swapped.push((binary_path, backed_up)),
+            Err(e) => {
+                for (binary_path, backed_up) in swapped.into_iter().rev() {
+                    restore_binary(binary_path, backed_up);
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// move new binary into place, and return true if the old binary was moved to `.old`
+fn swap_binary(new_path: &Path, binary_path: &Path) -> anyhow::Result<bool> {
+    let backed_up = binary_path.exists();
+    if backed_up {
+        fs::rename(binary_path, backup_path(binary_path)).map_err(|e| DbangError::from_io(binary_path, e))?;
+    }
+    if let Err(e) = fs::rename(new_path, binary_path) {
+        restore_binary(binary_path, backed_up);
+        return Err(DbangError::from_io(binary_path, e));
+    }
+    Ok(backed_up)
+}
+
+fn restore_binary(binary_path: &Path, backed_up: bool) {
+    if backed_up {
+        let _ = fs::rename(backup_path(binary_path), binary_path);
+    } else {
+        let _ = fs::remove_file(binary_path);
+    }
+}
+
+/// update dbang and dbang-shim to latest release, return the new version or None if up to date
+pub fn update(current_version: &str, force: bool) -> anyhow::Result<Option<String>> {
+    let exe_path = std::env::current_exe()?.canonicalize()?;
+    let install_method = InstallMethod::detect(&exe_path);
+    if let Some(command) = install_method.update_command() {
+        bail!("{} is not installed from GitHub releases, please update it by {}", exe_path.to_string_lossy(), command);
+    }
+    let release = fetch_latest_release()?;
+    let latest_version = release.tag_name.trim_start_matches('v').to_string();
+    if !force && deno_versions::parse_version(&latest_version) <= deno_versions::parse_version(current_version) {
+        return Ok(None);
+    }
+    let name = asset_name(&latest_version, &host_triple());
+    let find_asset = |asset_name: &str| release.assets.iter().find(|asset| asset.name == asset_name);
+    let asset = find_asset(&name)
+        .ok_or_else(|| DbangError::not_found(format!("Release asset {}", name), "Please download DBang from https://github.com/dbangdev/dbang/releases"))?;
+    let checksum_asset = find_asset(&format!("{}.sha256", name))
+        .ok_or_else(|| DbangError::not_found(format!("Checksum of {}", name), "Please download DBang from https://github.com/dbangdev/dbang/releases"))?;
+    let checksum_text = String::from_utf8(download(&checksum_asset.browser_download_url)?)?;
+    let tar_data = download(&asset.browser_download_url)?;
+    verify_checksum(&tar_data, &checksum_text)?;
+    let binaries = extract_binaries(&tar_data)?;
+    let bin_dir = exe_path.parent().ok_or_else(|| anyhow!("Unable to find dir of {}", exe_path.to_string_lossy()))?;
+    replace_binaries(bin_dir, &binaries)?;
+    Ok(Some(latest_version))
+}
+
+/// restore binaries kept by last update
+pub fn rollback() -> anyhow::Result<()> {
+    rollback_binaries(&installed_bin_dir()?)
+}
+
+/// replace binaries with `.old` ones together, and current binaries become `.old` ones
+fn rollback_binaries(bin_dir: &Path) -> anyhow::Result<()> {
+    let mut binaries = Vec::new();
+    for name in BINARY_NAMES {
+        let file_name = binary_file_name(name);
+        let backup_file = backup_path(&bin_dir.join(&file_name));
+        if !backup_file.exists() {
+            return Err(DbangError::not_found(format!("Backup {}", backup_file.to_string_lossy()),
+                                             "Rollback is available after `dbang self-update` only").into());
+        }
+        let data = fs::read(&backup_file).map_err(|e| DbangError::from_io(&backup_file, e))?;
+        binaries.push((file_name, data));
+    }
+    let result = replace_binaries(bin_dir, &binaries);
+    if result.is_err() {
+        // `.old` ones moved back by restore are written again, and rollback can be retried
+        for (file_name, data) in &binaries {
+            let backup_file = backup_path(&bin_dir.join(file_name));
+            if !backup_file.exists() {
+                let _ = fs::write(&backup_file, data);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_method() {
+        let cargo_dbang = dbang_utils::expand_home("~/.cargo/bin/dbang").unwrap();
+        if std::env::var("CARGO_HOME").is_err() {
+            assert_eq!(InstallMethod::detect(&cargo_dbang), InstallMethod::Cargo);
+        }
+        assert_eq!(InstallMethod::detect(Path::new("/opt/homebrew/Cellar/dbang/0.2.2/bin/dbang")), InstallMethod::Homebrew);
+        assert_eq!(InstallMethod::detect(Path::new("/usr/bin/dbang")), InstallMethod::PackageManager);
+        assert_eq!(InstallMethod::detect(&dbang_utils::expand_home("~/.dbang/bin/dbang").unwrap()), InstallMethod::Standalone);
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let checksum = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9  hello.tar";
+        assert!(verify_checksum(b"hello world", checksum).is_ok());
+        assert!(verify_checksum(b"hello dbang", checksum).is_err());
+    }
+
+    #[test]
+    fn test_extract_and_replace_binaries() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for name in BINARY_NAMES {
+            let data = format!("new {}", name);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, format!("dbang-0.3.0/bin/{}", binary_file_name(name)), data.as_bytes()).unwrap();
+        }
+        let binaries = extract_binaries(&builder.into_inner().unwrap()).unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let bin_dir = temp_dir.path();
+        let dbang_path = bin_dir.join(binary_file_name("dbang"));
+        fs::write(&dbang_path, "old dbang").unwrap();
+        replace_binaries(bin_dir, &binaries).unwrap();
+        assert_eq!(fs::read_to_string(&dbang_path).unwrap(), "new dbang");
+        assert_eq!(fs::read_to_string(backup_path(&dbang_path)).unwrap(), "old dbang");
+
+        // dbang-shim can't be moved to `.old`, and dbang is restored
+        fs::write(&dbang_path, "old dbang").unwrap();
+        let shim_path = bin_dir.join(binary_file_name("dbang-shim"));
+        fs::write(&shim_path, "old dbang-shim").unwrap();
+        fs::create_dir_all(backup_path(&shim_path).join("busy")).unwrap();
+        assert!(replace_binaries(bin_dir, &binaries).is_err());
+        assert_eq!(fs::read_to_string(&dbang_path).unwrap(), "old dbang");
+        assert_eq!(fs::read_to_string(&shim_path).unwrap(), "old dbang-shim");
+        assert!(!bin_dir.join(format!(".{}.new", binary_file_name("dbang"))).exists());
+        assert!(!bin_dir.join(format!(".{}.new", binary_file_name("dbang-shim"))).exists());
+    }
+
+    #[test]
+    fn test_rollback_binaries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let bin_dir = temp_dir.path();
+        let dbang_path = bin_dir.join(binary_file_name("dbang"));
+        let shim_path = bin_dir.join(binary_file_name("dbang-shim"));
+        fs::write(&dbang_path, "new dbang").unwrap();
+        fs::write(&shim_path, "new dbang-shim").unwrap();
+        fs::write(backup_path(&dbang_path), "old dbang").unwrap();
+        assert!(rollback_binaries(bin_dir).is_err());
+        assert_eq!(fs::read_to_string(&dbang_path).unwrap(), "new dbang");
+
+        // dbang-shim can't be moved to `.old`, and dbang is restored with its `.old` kept
+        fs::write(backup_path(&shim_path), "old dbang-shim").unwrap();
+        fs::remove_file(&shim_path).unwrap();
+        fs::create_dir_all(shim_path.join("busy")).unwrap();
+        assert!(rollback_binaries(bin_dir).is_err());
+        assert_eq!(fs::read_to_string(&dbang_path).unwrap(), "new dbang");
+        assert_eq!(fs::read_to_string(backup_path(&dbang_path)).unwrap(), "old dbang");
+        assert_eq!(fs::read_to_string(backup_path(&shim_path)).unwrap(), "old dbang-shim");
+
+        fs::remove_dir_all(&shim_path).unwrap();
+        fs::write(&shim_path, "new dbang-shim").unwrap();
+        rollback_binaries(bin_dir).unwrap();
+        assert_eq!(fs::read_to_string(&dbang_path).unwrap(), "old dbang");
+        assert_eq!(fs::read_to_string(&shim_path).unwrap(), "old dbang-shim");
+        assert_eq!(fs::read_to_string(backup_path(&dbang_path)).unwrap(), "new dbang");
+    }
+}
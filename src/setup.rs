@@ -0,0 +1,86 @@
+//! PATH setup for bin dirs of apps
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn from_name(name: &str) -> Option<Shell> {
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None,
+        }
+    }
+
+    /// detect user's shell from $SHELL
+    pub fn detect() -> Option<Shell> {
+        let shell = std::env::var("SHELL").ok()?;
+        let shell_name = Path::new(&shell).file_name()?.to_string_lossy().to_string();
+        Shell::from_name(&shell_name)
+    }
+
+    pub fn rc_file(&self) -> anyhow::Result<PathBuf> {
+        let home_dir = crate::dbang_utils::home_dir()?;
+        Ok(match self {
+            Shell::Bash => home_dir.join(".bashrc"),
+            Shell::Zsh => home_dir.join(".zshrc"),
+            Shell::Fish => home_dir.join(".config").join("fish").join("config.fish"),
+        })
+    }
+
+    /// line to add dir to PATH, and dir under home is written with $HOME
+    pub fn path_line(&self, dir: &Path) -> String {
+        let dir_text = match dirs::home_dir().and_then(|home_dir| dir.strip_prefix(home_dir).ok().map(|x| x.to_path_buf())) {
+            Some(relative_dir) => format!("$HOME/{}", relative_dir.to_string_lossy()),
+            None => dir.to_string_lossy().to_string(),
+        };
+        match self {
+            Shell::Bash | Shell::Zsh => format!("export PATH=\"{}:$PATH\"", dir_text),
+            Shell::Fish => format!("set -gx PATH \"{}\" $PATH", dir_text),
+        }
+    }
+}
+
+pub fn is_in_path(dir: &Path) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|path| path == dir))
+        .unwrap_or(false)
+}
+
+/// append lines to rc file of shell, lines already in rc file are skipped, and return lines appended
+pub fn append_path_lines(shell: Shell, lines: &[String]) -> anyhow::Result<Vec<String>> {
+    let rc_file = shell.rc_file()?;
+    let content = fs::read_to_string(&rc_file).unwrap_or_default();
+    let new_lines: Vec<String> = lines.iter().filter(|line| !content.contains(line.as_str())).cloned().collect();
+    if !new_lines.is_empty() {
+        if let Some(parent) = rc_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&rc_file)?;
+        writeln!(file, "\n# DBang apps")?;
+        for line in &new_lines {
+            writeln!(file, "{}", line)?;
+        }
+    }
+    Ok(new_lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_line() {
+        let dir = dirs::home_dir().unwrap().join(".dbang").join("bin");
+        assert_eq!(Shell::Zsh.path_line(&dir), "export PATH=\"$HOME/.dbang/bin:$PATH\"");
+        assert_eq!(Shell::Fish.path_line(Path::new("/opt/dbang/bin")), "set -gx PATH \"/opt/dbang/bin\" $PATH");
+    }
+}
@@ -0,0 +1,313 @@
+//! Trust rules of catalogs in ~/.dbang/trust.json
+//!
+//! Patterns are `user/repo` with `*` wildcards in segments, e.g. `my-org/*` or `*/dbang-catalog`,
+//! and host-qualified patterns such as `github.com/my-org/*`. Rules with `never` level deny catalogs before
+//! any other rules, otherwise the most specific rule wins.
+use std::fmt;
+use std::path::PathBuf;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::catalog::Catalog;
+use crate::catalog_sign::PublicKey;
+use crate::dbang_utils;
+use crate::errors::{self, DbangError};
+
+/// version of trust.json format
+pub const TRUST_FILE_VERSION: u32 = 1;
+/// host of catalogs, and patterns without host are for this host
+pub const DEFAULT_HOST: &str = "github.com";
+
+/// trust levels from the most trusted to the strictest
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrustLevel {
+    /// accept catalog and permission changes without prompt
+    AutoAccept,
+    /// accept catalog without prompt, and prompt if permissions of scripts changed
+    PromptOnChange,
+    /// prompt to accept catalog and changes, default for catalogs without rules
+    Prompt,
+    /// refuse catalog
+    Never,
+}
+
+impl TrustLevel {
+    pub const NAMES: [&'static str; 4] = ["auto-accept", "prompt-on-change", "prompt", "never"];
+
+    pub fn from_name(name: &str) -> Option<TrustLevel> {
+        match name {
+            "auto-accept" => Some(TrustLevel::AutoAccept),
+            "prompt-on-change" => Some(TrustLevel::PromptOnChange),
+            "prompt" => Some(TrustLevel::Prompt),
+            "never" => Some(TrustLevel::Never),
+            _ => None,
+        }
+    }
+
+    /// prompt is required or not for catalog or app changes
+    pub fn requires_confirm(&self, permissions_changed: bool) -> bool {
+        match self {
+            TrustLevel::AutoAccept => false,
+            TrustLevel::PromptOnChange => permissions_changed,
+            TrustLevel::Prompt | TrustLevel::Never => true,
+        }
+    }
+}
+
+impl fmt::Display for TrustLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TrustLevel::AutoAccept => "auto-accept",
+            TrustLevel::PromptOnChange => "prompt-on-change",
+            TrustLevel::Prompt => "prompt",
+            TrustLevel::Never => "never",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrustRule {
+    pub pattern: String,
+    pub level: TrustLevel,
+    /// pinned public key in base64, and catalogs must be signed by the key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl TrustRule {
+    pub fn new(pattern: &str, level: TrustLevel) -> TrustRule {
+        TrustRule { pattern: normalize_pattern(pattern), level, key: None }
+    }
+
+    /// pattern with host, e.g. `github.com/my-org/*`
+    fn qualified_pattern(&self) -> String {
+        if self.pattern.matches('/').count() >= 2 {
+            self.pattern.clone()
+        } else {
+            format!("{}/{}", DEFAULT_HOST, self.pattern)
+        }
+    }
+
+    pub fn matches(&self, catalog_full_name: &str) -> bool {
+        let regex_text = regex::escape(&self.qualified_pattern()).replace(r"\*", "[^/]*");
+        Regex::new(&format!("^{}$", regex_text))
+            .map(|regex| regex.is_match(&format!("{}/{}", DEFAULT_HOST, catalog_full_name)))
+            .unwrap_or(false)
+    }
+
+    /// count of literal characters, and exact names are more specific than wildcards
+    fn specificity(&self) -> usize {
+        self.qualified_pattern().chars().filter(|c| *c != '*').count()
+    }
+
+    pub fn public_key(&self) -> anyhow::Result<Option<PublicKey>> {
+        self.key.as_deref().map(PublicKey::parse).transpose()
+    }
+}
+
+/// user name only is for default catalog repo, e.g. `linux-china` for `linux-china/dbang-catalog`
+pub fn normalize_pattern(pattern: &str) -> String {
+    if pattern.contains('/') {
+        pattern.to_string()
+    } else {
+        Catalog::get_full_repo_name(pattern)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrustRules {
+    pub version: u32,
+    pub rules: Vec<TrustRule>,
+}
+
+impl Default for TrustRules {
+    fn default() -> Self {
+        TrustRules { version: TRUST_FILE_VERSION, rules: vec![] }
+    }
+}
+
+impl TrustRules {
+    /// rule for catalog: `never` rules first, then the most specific rule
+    pub fn find(&self, catalog_full_name: &str) -> Option<&TrustRule> {
+        let matched: Vec<&TrustRule> = self.rules.iter().filter(|rule| rule.matches(catalog_full_name)).collect();
+        matched.iter().find(|rule| rule.level == TrustLevel::Never).copied()
+            .or_else(|| matched.iter().max_by_key(|rule| rule.specificity()).copied())
+    }
+
+    /// add rule, or replace the rule with same pattern, and the pinned key is kept if the new rule has no key.
+    /// Return the saved rule
+    pub fn add(&mut self, mut rule: TrustRule) -> &TrustRule {
+        match self.rules.iter().position(|x| x.pattern == rule.pattern) {
+            Some(index) => {
+                if rule.key.is_none() {
+                    rule.key = self.rules[index].key.take();
+                }
+                self.rules[index] = rule;
+                &self.rules[index]
+            }
+            None => {
+                self.rules.push(rule);
+                self.rules.last().unwrap()
+            }
+        }
+    }
+
+    /// pinned keys of all rules matching the catalog, from the most specific rule, so a more specific rule
+    /// without key doesn't turn off signature checking required by a broader rule
+    pub fn public_keys(&self, catalog_full_name: &str) -> anyhow::Result<Vec<PublicKey>> {
+        let mut matched: Vec<&TrustRule> = self.rules.iter().filter(|rule| rule.matches(catalog_full_name)).collect();
+        matched.sort_by_key(|rule| std::cmp::Reverse(rule.specificity()));
+        matched.iter().filter_map(|rule| rule.public_key().transpose()).collect()
+    }
+
+    pub fn remove(&mut self, pattern: &str) -> Option<TrustRule> {
+        let pattern = normalize_pattern(pattern);
+        let index = self.rules.iter().position(|rule| rule.pattern == pattern)?;
+        Some(self.rules.remove(index))
+    }
+}
+
+fn get_trust_file() -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?.join("trust.json"))
+}
+
+/// newline-separated trusted catalogs before trust.json, with optional pinned key after the name
+fn get_legacy_known_catalogs_file() -> anyhow::Result<PathBuf> {
+    Ok(dbang_utils::dbang_dir()?.join("known_catalogs"))
+}
+
+/// convert known_catalogs to auto-accept rules, and keep the old file as known_catalogs.bak
+fn migrate_known_catalogs() -> anyhow::Result<TrustRules> {
+    let legacy_file = get_legacy_known_catalogs_file()?;
+    let mut trust_rules = TrustRules::default();
+    for line in errors::read_to_string(&legacy_file)?.lines() {
+        let mut parts = line.split_whitespace();
+        if let Some(catalog_full_name) = parts.next() {
+            let mut rule = TrustRule::new(catalog_full_name, TrustLevel::AutoAccept);
+            rule.key = parts.next().map(|key| key.to_string());
+            trust_rules.add(rule);
+        }
+    }
+    save(&trust_rules)?;
+    std::fs::rename(&legacy_file, legacy_file.with_extension("bak"))?;
+    Ok(trust_rules)
+}
+
+pub fn load() -> anyhow::Result<TrustRules> {
+    let trust_file = get_trust_file()?;
+    if !trust_file.exists() {
+        if get_legacy_known_catalogs_file()?.exists() {
+            return migrate_known_catalogs();
+        }
+        return Ok(TrustRules::default());
+    }
+    let data = errors::read_to_string(&trust_file)?;
+    let trust_rules = serde_json::from_str(&data)
+        .map_err(|e| DbangError::Parse { file: trust_file.to_string_lossy().to_string(), reason: e.to_string() })?;
+    Ok(trust_rules)
+}
+
+pub fn save(trust_rules: &TrustRules) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dbang_utils::dbang_dir()?)?;
+    let data = dbang_utils::to_pretty_json(trust_rules, "  ")?;
+    std::fs::write(get_trust_file()?, data)?;
+    Ok(())
+}
+
+/// matched rule of catalog, None if no rules match
+pub fn find_rule(catalog_full_name: &str) -> anyhow::Result<Option<TrustRule>> {
+    Ok(load()?.find(catalog_full_name).cloned())
+}
+
+/// trust level of catalog, and `prompt` if no rules match
+pub fn level(catalog_full_name: &str) -> anyhow::Result<TrustLevel> {
+    Ok(find_rule(catalog_full_name)?.map(|rule| rule.level).unwrap_or(TrustLevel::Prompt))
+}
+
+/// trust level of catalog with its included catalogs, and the strictest level wins.
+/// Catalogs included by URL are not matched by rules, and they always prompt
+pub fn level_with_includes(catalog_full_name: &str, included_catalogs: &[String]) -> anyhow::Result<TrustLevel> {
+    let mut trust_level = level(catalog_full_name)?;
+    for included_catalog in included_catalogs {
+        let included_level = if included_catalog.starts_with("https://") || included_catalog.starts_with("http://") {
+            TrustLevel::Prompt
+        } else {
+            level(included_catalog)?
+        };
+        trust_level = trust_level.max(included_level);
+    }
+    Ok(trust_level)
+}
+
+/// pinned public keys of catalog, and the catalog must be signed by one of them if any
+pub fn public_keys(catalog_full_name: &str) -> anyhow::Result<Vec<PublicKey>> {
+    load()?.public_keys(catalog_full_name)
+}
+
+/// refuse catalog denied by a `never` rule
+pub fn ensure_not_denied(catalog_full_name: &str) -> anyhow::Result<()> {
+    if let Some(rule) = find_rule(catalog_full_name)? {
+        if rule.level == TrustLevel::Never {
+            return Err(DbangError::Denied { catalog: catalog_full_name.to_string(), rule: rule.pattern }.into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(TrustRule::new("linux-china", TrustLevel::AutoAccept).matches("linux-china/dbang-catalog"));
+        assert!(!TrustRule::new("linux-china", TrustLevel::AutoAccept).matches("linux-china/other"));
+        assert!(TrustRule::new("my-org/*", TrustLevel::AutoAccept).matches("my-org/tools"));
+        assert!(!TrustRule::new("my-org/*", TrustLevel::AutoAccept).matches("my-org-fork/tools"));
+        assert!(TrustRule::new("*/dbang-catalog", TrustLevel::Prompt).matches("jack/dbang-catalog"));
+        assert!(TrustRule::new("github.com/my-org/*", TrustLevel::AutoAccept).matches("my-org/tools"));
+        assert!(!TrustRule::new("gitlab.com/my-org/*", TrustLevel::AutoAccept).matches("my-org/tools"));
+        assert!(!TrustRule::new("my.org/tools", TrustLevel::AutoAccept).matches("myxorg/tools"));
+    }
+
+    #[test]
+    fn test_find() {
+        let mut trust_rules = TrustRules::default();
+        trust_rules.add(TrustRule::new("my-org/*", TrustLevel::PromptOnChange));
+        trust_rules.add(TrustRule::new("my-org/tools", TrustLevel::AutoAccept));
+        trust_rules.add(TrustRule::new("*/*", TrustLevel::Prompt));
+        assert_eq!(trust_rules.find("my-org/tools").unwrap().pattern, "my-org/tools");
+        assert_eq!(trust_rules.find("my-org/scripts").unwrap().pattern, "my-org/*");
+        assert_eq!(trust_rules.find("jack/scripts").unwrap().pattern, "*/*");
+        // deny rule wins over more specific rules
+        trust_rules.add(TrustRule::new("*/tools", TrustLevel::Never));
+        assert_eq!(trust_rules.find("my-org/tools").unwrap().level, TrustLevel::Never);
+        assert_eq!(trust_rules.remove("*/tools").unwrap().level, TrustLevel::Never);
+        assert_eq!(trust_rules.find("my-org/tools").unwrap().level, TrustLevel::AutoAccept);
+    }
+
+    #[test]
+    fn test_public_keys() {
+        let key = crate::catalog_sign::SecretKey::generate().public_key();
+        let mut trust_rules = TrustRules::default();
+        let mut rule = TrustRule::new("my-org/*", TrustLevel::Prompt);
+        rule.key = Some(key.to_base64());
+        trust_rules.add(rule);
+        trust_rules.add(TrustRule::new("my-org/tools", TrustLevel::AutoAccept));
+        // more specific rule without key doesn't drop the key of the org
+        assert_eq!(trust_rules.public_keys("my-org/tools").unwrap(), vec![key]);
+        assert!(trust_rules.public_keys("other/tools").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_add_keeps_key() {
+        let mut trust_rules = TrustRules::default();
+        let mut rule = TrustRule::new("my-org/*", TrustLevel::Prompt);
+        rule.key = Some("RWQ".to_string());
+        trust_rules.add(rule);
+        let rule = trust_rules.add(TrustRule::new("my-org/*", TrustLevel::AutoAccept));
+        assert_eq!((rule.level, rule.key.as_deref()), (TrustLevel::AutoAccept, Some("RWQ")));
+        assert_eq!(trust_rules.rules.len(), 1);
+    }
+}
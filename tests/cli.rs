@@ -0,0 +1,624 @@
+//! End to end tests of dbang, with a local HTTP server standing in for GitHub and stub deno
+#![cfg(unix)]
+
+mod common;
+
+use common::{assert_success, stdout, TestEnv, DENO_VERSION, HEAD_COMMIT};
+
+const CATALOG_NAME: &str = "linux-china/dbang-catalog";
+
+#[test]
+fn test_catalog_add_update_delete() {
+    let env = TestEnv::new();
+    let output = env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n");
+    assert_success(&output);
+    assert!(stdout(&output).contains("Catalog added successfully!"));
+    let catalog_text = std::fs::read_to_string(env.catalog_file(CATALOG_NAME)).unwrap();
+    assert!(catalog_text.contains("hello.ts"));
+    // scripts are cached by deno after catalog accepted
+    let hello_url = format!("{}/raw/{}/HEAD/hello.ts", env.server.base_url, CATALOG_NAME);
+    assert!(env.deno_calls().iter().any(|call| call.starts_with("cache") && call.contains(&hello_url)), "{:?}", env.deno_calls());
+
+    let output = env.dbang(&["catalog", "list"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains(CATALOG_NAME));
+
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json",
+                   common::HELLO_CATALOG.replace("Hello world", "Hello updated"));
+    assert_success(&env.dbang_with_input(&["catalog", "update", "linux-china"], "y\n"));
+    let catalog_text = std::fs::read_to_string(env.catalog_file(CATALOG_NAME)).unwrap();
+    assert!(catalog_text.contains("Hello updated"));
+
+    assert_success(&env.dbang(&["catalog", "delete", "linux-china"]));
+    assert!(!env.catalog_file(CATALOG_NAME).exists());
+}
+
+#[test]
+fn test_catalog_rejected() {
+    let env = TestEnv::new();
+    let output = env.dbang_with_input(&["catalog", "add", "linux-china"], "n\n");
+    assert_success(&output);
+    assert!(stdout(&output).contains("Abort to accept dbang catalog!"));
+    assert!(!env.catalog_file(CATALOG_NAME).exists());
+}
+
+#[test]
+fn test_trusted_catalog() {
+    let env = TestEnv::new();
+    assert_success(&env.dbang(&["trust", "add", "linux-china"]));
+    let output = env.dbang(&["trust", "list"]);
+    assert!(stdout(&output).contains(CATALOG_NAME));
+    // no prompt for trusted catalog
+    let output = env.dbang(&["catalog", "add", "linux-china"]);
+    assert_success(&output);
+    assert!(!stdout(&output).contains("Do you accept above catalog?"));
+    assert!(env.catalog_file(CATALOG_NAME).exists());
+    assert_success(&env.dbang(&["trust", "delete", "linux-china"]));
+    let output = env.dbang(&["trust", "list", "linux-china"]);
+    assert!(stdout(&output).contains("no rule matched"), "{}", stdout(&output));
+}
+
+#[test]
+fn test_migrate_known_catalogs() {
+    let env = TestEnv::new();
+    std::fs::create_dir_all(env.dbang_home()).unwrap();
+    std::fs::write(env.dbang_home().join("known_catalogs"), "linux-china/dbang-catalog\njack/scripts").unwrap();
+    let output = env.dbang(&["trust", "list"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("jack/scripts  auto-accept"), "{}", stdout(&output));
+    assert!(!env.dbang_home().join("known_catalogs").exists());
+    assert!(env.dbang_home().join("known_catalogs.bak").exists());
+    let trust_rules: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(env.dbang_home().join("trust.json")).unwrap()).unwrap();
+    assert_eq!(trust_rules["rules"][0]["pattern"], CATALOG_NAME);
+    assert_eq!(trust_rules["rules"][0]["level"], "auto-accept");
+}
+
+#[test]
+fn test_doctor_refetch_catalog() {
+    let env = TestEnv::new();
+    assert_success(&env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n"));
+    std::fs::write(env.catalog_file(CATALOG_NAME), "{").unwrap();
+    // corrupt catalog is fetched again only if accepted
+    let output = env.dbang_with_input(&["doctor", "--fix"], "n\n");
+    assert!(stdout(&output).contains("fix failed: Abort to accept dbang catalog"), "{}", stdout(&output));
+    assert_eq!(std::fs::read_to_string(env.catalog_file(CATALOG_NAME)).unwrap(), "{");
+    let output = env.dbang_with_input(&["doctor", "--fix"], "y\n");
+    assert!(stdout(&output).contains("Do you accept above catalog?"), "{}", stdout(&output));
+    assert!(std::fs::read_to_string(env.catalog_file(CATALOG_NAME)).unwrap().contains("hello"));
+    // Deno cache of the catalog is checked
+    let output = env.dbang(&["doctor"]);
+    assert!(stdout(&output).contains("Deno cache of linux-china/dbang-catalog: 1 of 1 scripts cached"), "{}", stdout(&output));
+    assert_success(&env.dbang(&["cache", "clean", "linux-china"]));
+    let output = env.dbang(&["doctor"]);
+    assert!(stdout(&output).contains("0 of 1 scripts cached, 0 B, please use `dbang cache warm linux-china/dbang-catalog`"), "{}", stdout(&output));
+}
+
+#[test]
+fn test_wildcard_and_deny_rules() {
+    let env = TestEnv::new();
+    assert_success(&env.dbang(&["trust", "add", "linux-china/*"]));
+    let output = env.dbang(&["catalog", "add", "linux-china"]);
+    assert_success(&output);
+    assert!(!stdout(&output).contains("Do you accept above catalog?"));
+    let output = env.dbang(&["trust", "list", "linux-china"]);
+    assert!(stdout(&output).contains("auto-accept by rule linux-china/*"), "{}", stdout(&output));
+
+    assert_success(&env.dbang(&["trust", "add", "*/dbang-catalog", "--level", "never"]));
+    let output = env.dbang(&["run", "hello@linux-china"]);
+    assert_eq!(output.status.code(), Some(77));
+    let output = env.dbang(&["catalog", "update", "linux-china"]);
+    assert_eq!(output.status.code(), Some(77));
+    // deny rule is kept after catalog deleted
+    assert_success(&env.dbang(&["catalog", "delete", "linux-china"]));
+    assert!(stdout(&env.dbang(&["trust", "list"])).contains("*/dbang-catalog  never"));
+}
+
+#[test]
+fn test_prompt_on_permission_change() {
+    let env = TestEnv::new();
+    assert_success(&env.dbang(&["trust", "add", "linux-china", "--level", "prompt-on-change"]));
+    assert_success(&env.dbang(&["catalog", "add", "linux-china"]));
+    // description changed only, and no prompt
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json",
+                   common::HELLO_CATALOG.replace("Hello world", "Hello updated"));
+    let output = env.dbang(&["catalog", "update", "linux-china"]);
+    assert!(stdout(&output).contains("Catalog updated successfully!"), "{}", stdout(&output));
+    // permissions changed, and rejected
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json",
+                   common::HELLO_CATALOG.replace("allow-env", "allow-all"));
+    let output = env.dbang_with_input(&["catalog", "update", "linux-china"], "n\n");
+    assert!(stdout(&output).contains("hello: --allow-env -> --allow-all"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("Abort to accept dbang catalog!"));
+    assert!(!std::fs::read_to_string(env.catalog_file(CATALOG_NAME)).unwrap().contains("allow-all"));
+}
+
+#[test]
+fn test_run() {
+    let env = TestEnv::new();
+    let output = env.dbang_with_input(&["run", "hello@linux-china", "world"], "y\n");
+    assert_success(&output);
+    let hello_url = format!("{}/raw/{}/HEAD/hello.ts", env.server.base_url, CATALOG_NAME);
+    let run_call = env.deno_calls().into_iter().find(|call| call.starts_with("run")).expect("deno run not called");
+    assert!(run_call.starts_with("run --no-check --cached-only"), "{}", run_call);
+    assert!(run_call.contains("--allow-env"), "{}", run_call);
+    assert!(run_call.ends_with(&format!("{} world", hello_url)), "{}", run_call);
+}
+
+#[test]
+fn test_run_verbose_to_stderr() {
+    let env = TestEnv::new();
+    let output = env.dbang_with_input(&["--verbose", "run", "hello@linux-china"], "y\n");
+    assert_success(&output);
+    // debug info doesn't mix with stdout of the script
+    assert!(!stdout(&output).contains("[dbang]"), "{}", stdout(&output));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[dbang] begin to run hello@linux-china"), "{}", stderr);
+    assert!(stderr.contains("[dbang] command line:"), "{}", stderr);
+}
+
+#[test]
+fn test_dropped_flags_warning() {
+    let env = TestEnv::new();
+    let catalog = r#"{
+  "scripts": {
+    "hello": {"script-ref": "hello.ts", "compat": true, "permissions": ["allow-env"]}
+  }
+}"#;
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", catalog);
+    assert_success(&env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n"));
+    // warning without --verbose, and it doesn't mix with stdout of the script
+    let output = env.dbang(&["run", "hello@linux-china"]);
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Warning: --compat dropped for Deno 1.46.3"), "{}", stderr);
+    assert!(!stdout(&output).contains("--compat"), "{}", stdout(&output));
+    let run_call = env.deno_calls().into_iter().find(|call| call.starts_with("run")).unwrap();
+    assert!(!run_call.contains("--compat"), "{}", run_call);
+}
+
+#[test]
+fn test_run_unknown_script() {
+    let env = TestEnv::new();
+    let output = env.dbang_with_input(&["run", "missing@linux-china"], "y\n");
+    assert_eq!(output.status.code(), Some(66));
+}
+
+#[test]
+fn test_install_and_run_app() {
+    let env = TestEnv::new();
+    assert_success(&env.dbang(&["trust", "add", "linux-china"]));
+    assert_success(&env.dbang(&["catalog", "add", "linux-china"]));
+    let output = env.dbang(&["install", "hello@linux-china"]);
+    assert_success(&output);
+    let apps: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(env.dbang_home().join("apps.json")).unwrap()).unwrap();
+    assert_eq!(apps["apps"]["hello"]["script"], "hello@linux-china");
+    assert_eq!(apps["apps"]["hello"]["ref"], HEAD_COMMIT);
+    assert_eq!(apps["apps"]["hello"]["permissions"][0], "--allow-env");
+
+    let app_link = env.dbang_home().join("bin/hello");
+    assert!(app_link.symlink_metadata().unwrap().file_type().is_symlink());
+    let output = env.command(&app_link).arg("world").output().unwrap();
+    assert_success(&output);
+    let run_call = env.deno_calls().into_iter().find(|call| call.starts_with("run")).expect("deno run not called");
+    assert!(run_call.ends_with("hello.ts world"), "{}", run_call);
+
+    assert_success(&env.dbang(&["uninstall", "hello"]));
+    assert!(app_link.symlink_metadata().is_err());
+
+    // unknown apps and reserved names are refused, and files in bin dir are kept
+    let deno_link = env.dbang_home().join("bin/deno");
+    std::fs::write(&deno_link, "").unwrap();
+    assert_eq!(env.dbang(&["uninstall", "deno"]).status.code(), Some(1));
+    assert_eq!(env.dbang(&["uninstall", "../deno"]).status.code(), Some(1));
+    assert_eq!(env.dbang(&["uninstall", "hello"]).status.code(), Some(66));
+    assert!(deno_link.exists());
+}
+
+#[test]
+fn test_upgrade_app_with_catalog_changes() {
+    let env = TestEnv::new();
+    assert_success(&env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n"));
+    assert_success(&env.dbang(&["install", "hello@linux-china"]));
+    let catalog = r#"{
+  "scripts": {
+    "hello": {"script-ref": "hello.ts", "description": "Hello"},
+    "other": {"script-ref": "other.ts", "permissions": ["allow-all"]}
+  }
+}"#;
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", catalog);
+
+    // permission changes of other scripts are shown, and the catalog is kept if rejected
+    let output = env.dbang_with_input(&["apps", "upgrade", "hello"], "n\n");
+    assert_success(&output);
+    assert!(stdout(&output).contains("other: none -> --allow-all"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("upgrade aborted"), "{}", stdout(&output));
+    assert!(!std::fs::read_to_string(env.catalog_file(CATALOG_NAME)).unwrap().contains("other"));
+
+    assert_success(&env.dbang_with_input(&["apps", "upgrade", "hello"], "y\n"));
+    assert!(std::fs::read_to_string(env.catalog_file(CATALOG_NAME)).unwrap().contains("other"));
+    let output = env.dbang(&["log", "--catalog", "linux-china"]);
+    assert!(stdout(&output).contains("upgrade of hello rejected by user"), "{}", stdout(&output));
+    assert_eq!(stdout(&output).matches("accepted by user").count(), 2, "{}", stdout(&output));
+}
+
+#[test]
+fn test_deno_add_list_delete() {
+    let env = TestEnv::new();
+    let output = env.dbang(&["deno", "add", DENO_VERSION, "--default"]);
+    assert_success(&output);
+    let deno_bin = env.dbang_home().join("deno").join(DENO_VERSION).join("deno");
+    assert!(deno_bin.exists());
+    let deno_link = env.dbang_home().join("bin/deno");
+    assert_eq!(std::fs::canonicalize(&deno_link).unwrap(), std::fs::canonicalize(&deno_bin).unwrap());
+
+    let output = env.dbang(&["deno", "list"]);
+    assert!(stdout(&output).contains(&format!("* {}", DENO_VERSION)), "{}", stdout(&output));
+
+    assert_success(&env.dbang(&["deno", "delete", DENO_VERSION]));
+    assert!(!deno_bin.exists());
+}
+
+#[test]
+fn test_deno_add_unknown_version() {
+    let env = TestEnv::new();
+    let output = env.dbang(&["deno", "add", "0.0.1"]);
+    assert_eq!(output.status.code(), Some(66));
+    assert!(!env.dbang_home().join("deno/0.0.1").exists());
+}
+
+#[test]
+fn test_offline_without_cache() {
+    let env = TestEnv::new();
+    let output = env.dbang(&["--offline", "run", "hello@linux-china"]);
+    assert_eq!(output.status.code(), Some(75));
+    assert!(env.server.requests().is_empty(), "{:?}", env.server.requests());
+}
+
+#[test]
+fn test_signed_catalog() {
+    let env = TestEnv::new();
+    let author_dir = env.dir.path().join("author");
+    std::fs::create_dir_all(&author_dir).unwrap();
+    let catalog_file = author_dir.join("dbang-catalog.json");
+    std::fs::write(&catalog_file, common::HELLO_CATALOG).unwrap();
+    let key_file = author_dir.join("author.key");
+    let catalog_arg = catalog_file.to_string_lossy().to_string();
+    let key_arg = key_file.to_string_lossy().to_string();
+    assert_success(&env.dbang(&["catalog", "sign", &catalog_arg, "--key", &key_arg, "--generate-key", "--repo", "linux-china"]));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&key_file).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    let signature = std::fs::read(author_dir.join("dbang-catalog.json.minisig")).unwrap();
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json.minisig", signature.clone());
+
+    let public_key_arg = author_dir.join("author.pub").to_string_lossy().to_string();
+    assert_success(&env.dbang(&["trust", "add", "linux-china", "--key", &public_key_arg]));
+    assert!(stdout(&env.dbang(&["trust", "list"])).contains("signed by key"));
+    assert_success(&env.dbang(&["catalog", "add", "linux-china"]));
+    assert!(env.catalog_file(CATALOG_NAME).exists());
+    // re-added rule without key keeps the pinned key
+    let output = env.dbang(&["trust", "add", "linux-china", "--level", "auto-accept"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("is kept"), "{}", stdout(&output));
+    assert!(stdout(&env.dbang(&["trust", "list"])).contains("signed by key"));
+
+    // tampered catalog is refused, and local catalog is kept
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json",
+                   common::HELLO_CATALOG.replace("allow-env", "allow-all"));
+    let output = env.dbang(&["catalog", "update", "linux-china"]);
+    assert_eq!(output.status.code(), Some(76));
+    let catalog_text = std::fs::read_to_string(env.catalog_file(CATALOG_NAME)).unwrap();
+    assert!(!catalog_text.contains("allow-all"));
+
+    // signature of other repo signed by the same key is refused
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", common::HELLO_CATALOG);
+    assert_success(&env.dbang(&["catalog", "sign", &catalog_arg, "--key", &key_arg, "--repo", "linux-china/other"]));
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json.minisig",
+                   std::fs::read(author_dir.join("dbang-catalog.json.minisig")).unwrap());
+    let output = env.dbang(&["catalog", "update", "linux-china"]);
+    assert_eq!(output.status.code(), Some(76));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("signature is for linux-china/other/dbang-catalog.json"),
+            "{}", String::from_utf8_lossy(&output.stderr));
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json.minisig", signature);
+
+    // signature older than the verified one is refused against rollback
+    let signatures_file = env.dbang_home().join("signatures.json");
+    let mut timestamps: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&signatures_file).unwrap()).unwrap();
+    let signed_file = format!("{}/dbang-catalog.json", CATALOG_NAME);
+    assert!(timestamps[&signed_file].is_i64());
+    timestamps[&signed_file] = serde_json::json!(i64::MAX);
+    std::fs::write(&signatures_file, timestamps.to_string()).unwrap();
+    let output = env.dbang(&["catalog", "update", "linux-china"]);
+    assert_eq!(output.status.code(), Some(76));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("rollback"), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn test_script_sha256() {
+    use sha2::Digest;
+    let env = TestEnv::new();
+    let catalog = |sha256: &str| format!(r#"{{
+  "lazy-cache": true,
+  "scripts": {{
+    "hello": {{"script-ref": "hello.ts", "sha256": "{}"}}
+  }}
+}}"#, sha256);
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", catalog(&"0".repeat(64)));
+    assert_success(&env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n"));
+    // script content doesn't match SHA-256 in catalog, and it's not run
+    let output = env.dbang(&["run", "hello@linux-china"]);
+    assert_eq!(output.status.code(), Some(76));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("SHA-256 of hello is"), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!env.deno_calls().iter().any(|call| call.starts_with("run")));
+
+    let sha256 = format!("{:x}", sha2::Sha256::digest(common::HELLO_SCRIPT));
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", catalog(&sha256));
+    assert_success(&env.dbang_with_input(&["catalog", "update", "linux-china"], "y\n"));
+    assert_success(&env.dbang(&["run", "hello@linux-china"]));
+}
+
+#[test]
+fn test_signed_catalog_with_url_include() {
+    let env = TestEnv::new();
+    let catalog_file = env.dir.path().join("dbang-catalog.json");
+    let catalog = format!(r#"{{"includes": ["{}/tools/dbang-catalog.json"], "scripts": {{}}}}"#, env.server.base_url);
+    std::fs::write(&catalog_file, &catalog).unwrap();
+    let secret_key = env.dir.path().join("author.key").to_string_lossy().to_string();
+    assert_success(&env.dbang(&["catalog", "sign", &catalog_file.to_string_lossy(), "--key", &secret_key, "--generate-key", "--repo", "linux-china"]));
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", catalog);
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json.minisig", std::fs::read(env.dir.path().join("dbang-catalog.json.minisig")).unwrap());
+    env.server.add("/tools/dbang-catalog.json", r#"{"scripts": {"fmt": {"script-ref": "fmt.ts"}}}"#);
+    let public_key = env.dir.path().join("author.pub").to_string_lossy().to_string();
+    assert_success(&env.dbang(&["trust", "add", "linux-china", "--key", &public_key]));
+    // catalog included by URL can't be verified by the pinned key
+    let output = env.dbang(&["catalog", "add", "linux-china"]);
+    assert_eq!(output.status.code(), Some(76));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("can't be verified"), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!env.catalog_file(CATALOG_NAME).exists());
+}
+
+#[test]
+fn test_pinned_key_without_signature() {
+    let env = TestEnv::new();
+    let secret_key = env.dir.path().join("other.key").to_string_lossy().to_string();
+    let catalog_file = env.dir.path().join("dbang-catalog.json");
+    std::fs::write(&catalog_file, common::HELLO_CATALOG).unwrap();
+    assert_success(&env.dbang(&["catalog", "sign", &catalog_file.to_string_lossy(), "--key", &secret_key, "--generate-key", "--repo", "linux-china"]));
+    let public_key = env.dir.path().join("other.pub").to_string_lossy().to_string();
+    assert_success(&env.dbang(&["trust", "add", "linux-china/*", "--key", &public_key]));
+    // more specific rule without key still requires signature by key of the broader rule
+    assert_success(&env.dbang(&["trust", "add", "linux-china", "--level", "auto-accept"]));
+    let output = env.dbang(&["catalog", "add", "linux-china"]);
+    assert_eq!(output.status.code(), Some(76));
+    assert!(!env.catalog_file(CATALOG_NAME).exists());
+}
+
+#[test]
+fn test_audit_log() {
+    use sha2::Digest;
+    let env = TestEnv::new();
+    assert_success(&env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n"));
+    assert_success(&env.dbang(&["run", "hello@linux-china", "world"]));
+    assert_success(&env.dbang(&["trust", "add", "my-org/*"]));
+    assert_success(&env.dbang(&["install", "hello@linux-china"]));
+    assert_success(&env.dbang(&["uninstall", "hello"]));
+
+    let output = env.dbang(&["log", "--json"]);
+    assert_success(&output);
+    let entries: Vec<serde_json::Value> = stdout(&output).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let events: Vec<&str> = entries.iter().map(|entry| entry["event"].as_str().unwrap()).collect();
+    assert_eq!(events, vec!["catalog-accept", "run", "trust-add", "install", "uninstall"]);
+    let run = &entries[1];
+    assert_eq!(run["catalog"], CATALOG_NAME);
+    assert_eq!(run["script"], "hello");
+    assert_eq!(run["url"], format!("{}/raw/{}/HEAD/hello.ts", env.server.base_url, CATALOG_NAME));
+    assert_eq!(run["sha256"], format!("{:x}", sha2::Sha256::digest(common::HELLO_SCRIPT)));
+    assert_eq!(run["permissions"][0], "--allow-env");
+    assert_eq!(run["exit-code"], 0);
+    assert!(run["duration-ms"].is_u64());
+
+    let output = env.dbang(&["log", "--event", "run", "--catalog", "linux-china", "--since", "1h"]);
+    assert_eq!(stdout(&output).lines().count(), 1, "{}", stdout(&output));
+    assert!(stdout(&output).contains("hello@linux-china/dbang-catalog  exit 0"), "{}", stdout(&output));
+    let output = env.dbang(&["log", "--limit", "2"]);
+    assert_eq!(stdout(&output).lines().count(), 2);
+    assert!(stdout(&env.dbang(&["log", "--since", "2999-01-01"])).is_empty());
+}
+
+#[test]
+fn test_audit_log_of_rejected_catalog() {
+    let env = TestEnv::new();
+    env.dbang_with_input(&["catalog", "add", "linux-china"], "n\n");
+    assert_success(&env.dbang(&["trust", "add", "linux-china", "--level", "never"]));
+    assert_eq!(env.dbang(&["catalog", "add", "linux-china"]).status.code(), Some(77));
+    let output = env.dbang(&["log", "--event", "catalog-reject"]);
+    assert!(stdout(&output).contains("rejected by user"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("denied by trust rule"), "{}", stdout(&output));
+}
+
+#[test]
+fn test_lockfile() {
+    let env = TestEnv::new();
+    assert_success(&env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n"));
+    let lock_file = env.dbang_home().join("catalogs/github").join(CATALOG_NAME).join("locks/hello.json");
+    assert!(lock_file.exists());
+    let cache_call = env.deno_calls().into_iter().find(|call| call.starts_with("cache")).unwrap();
+    assert!(cache_call.contains("--lock-write"), "{}", cache_call);
+
+    assert_success(&env.dbang(&["run", "hello@linux-china"]));
+    let run_call = env.deno_calls().into_iter().find(|call| call.starts_with("run")).unwrap();
+    assert!(run_call.contains(&format!("--lock={} --frozen", lock_file.to_string_lossy())), "{}", run_call);
+
+    std::fs::write(&lock_file, "{}").unwrap();
+    let output = env.dbang(&["catalog", "relock", "linux-china"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("Lockfiles of linux-china/dbang-catalog refreshed!"));
+    assert_ne!(std::fs::read_to_string(&lock_file).unwrap(), "{}");
+    assert!(!lock_file.with_extension("json.new").exists());
+}
+
+#[test]
+fn test_deno_cache() {
+    let env = TestEnv::new();
+    assert_success(&env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n"));
+    let deno_dir = env.dbang_home().join("catalogs/github").join(CATALOG_NAME).join("deno_dir");
+    assert!(deno_dir.join("remote/module.ts").exists(), "script not cached in DENO_DIR of catalog");
+    let cache_calls = |env: &TestEnv| env.deno_calls().iter().filter(|call| call.starts_with("cache")).count();
+    assert_eq!(cache_calls(&env), 1);
+
+    // unchanged scripts are skipped
+    let output = env.dbang(&["cache", "warm", "linux-china"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("up to date"), "{}", stdout(&output));
+    assert_eq!(cache_calls(&env), 1);
+    // new commit of catalog repo without changes of the script
+    env.server.add("/api/repos/linux-china/dbang-catalog/commits/HEAD", "fedcba9876543210fedcba9876543210fedcba98");
+    let output = env.dbang(&["cache", "warm", "hello@linux-china"]);
+    assert!(stdout(&output).contains("hello@linux-china is up to date"), "{}", stdout(&output));
+    // changed content of script in the repo
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/hello.ts", "console.log('Hello again');");
+    let output = env.dbang(&["cache", "warm", "hello@linux-china"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("hello@linux-china cached"), "{}", stdout(&output));
+    assert_eq!(cache_calls(&env), 2);
+
+    let output = env.dbang(&["cache", "list"]);
+    assert!(stdout(&output).contains("1/1 scripts cached"), "{}", stdout(&output));
+    let output = env.dbang(&["cache", "size", "linux-china"]);
+    assert!(!stdout(&output).contains(": 0 B"), "{}", stdout(&output));
+
+    assert_success(&env.dbang(&["cache", "clean"]));
+    assert!(!deno_dir.exists());
+    let output = env.dbang(&["cache", "warm", "linux-china"]);
+    assert!(stdout(&output).contains("cached: hello"), "{}", stdout(&output));
+    assert!(deno_dir.join("remote/module.ts").exists());
+}
+
+#[test]
+fn test_cache_failures() {
+    let env = TestEnv::new();
+    let catalog = common::HELLO_CATALOG.replacen(r#""scripts": {"#, r#""scripts": {
+    "broken": {"script-ref": "broken.ts"},
+    "other-platform": {"script-ref": "other.ts", "platforms": ["plan9"]},"#, 1);
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", catalog);
+    let output = env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("[2/2]"), "{}", stdout(&output));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Failed to cache 1 of 2 scripts"), "{}", stderr);
+    assert!(stderr.contains("broken") && stderr.contains("error: Module not found"), "{}", stderr);
+    assert!(!env.deno_calls().iter().any(|call| call.contains("other.ts")));
+    // catalog is saved, and other scripts are cached
+    assert!(env.catalog_file(CATALOG_NAME).exists());
+    assert!(env.dbang_home().join("catalogs/github").join(CATALOG_NAME).join("locks/hello.json").exists());
+
+    // failed scripts are cached again by warming
+    let output = env.dbang(&["cache", "warm", "linux-china"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("[1/1] broken failed"), "{}", stdout(&output));
+}
+
+#[test]
+fn test_lazy_cache() {
+    let env = TestEnv::new();
+    let catalog = common::HELLO_CATALOG.replacen("{", r#"{
+  "lazy-cache": true,"#, 1);
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", catalog);
+    let calls_of = |env: &TestEnv, command: &str| env.deno_calls().iter().filter(|call| call.starts_with(command)).count();
+    assert_success(&env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n"));
+    assert_eq!(calls_of(&env, "cache"), 0);
+
+    // cached on first run only
+    let output = env.dbang(&["run", "hello@linux-china"]);
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("hello@linux-china not in Deno cache"));
+    assert_eq!(calls_of(&env, "cache"), 1);
+    assert_success(&env.dbang(&["run", "hello@linux-china"]));
+    assert_eq!((calls_of(&env, "cache"), calls_of(&env, "run")), (1, 2));
+
+    // cleaned cache is recovered by next run
+    assert_success(&env.dbang(&["cache", "clean", "linux-china"]));
+    assert_success(&env.dbang(&["run", "hello@linux-china"]));
+    assert_eq!((calls_of(&env, "cache"), calls_of(&env, "run")), (2, 3));
+    // modules removed from DENO_DIR by hand: failed `--cached-only` run is cached and retried once
+    let deno_dir = env.dbang_home().join("catalogs/github").join(CATALOG_NAME).join("deno_dir");
+    std::fs::remove_dir_all(deno_dir.join("remote")).unwrap();
+    let output = env.dbang(&["run", "hello@linux-china"]);
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("missing in Deno cache"));
+    assert_eq!((calls_of(&env, "cache"), calls_of(&env, "run")), (3, 5));
+    assert_success(&env.dbang(&["run", "hello@linux-china"]));
+    assert_eq!((calls_of(&env, "cache"), calls_of(&env, "run")), (3, 6));
+    // no caching in offline mode, and Deno never falls back to global Deno cache
+    assert_success(&env.dbang(&["cache", "clean"]));
+    let output = env.dbang(&["--offline", "run", "hello@linux-china"]);
+    assert_eq!(output.status.code(), Some(75));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("offline mode"));
+    assert_eq!((calls_of(&env, "cache"), calls_of(&env, "run")), (3, 6));
+}
+
+#[test]
+fn test_catalog_defaults() {
+    let env = TestEnv::new();
+    let catalog = r#"{
+  "defaults": {"permissions": ["allow-read"]},
+  "scripts": {
+    "hello": {"script-ref": "hello.ts"}
+  }
+}"#;
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", catalog);
+    assert_success(&env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n"));
+    assert_success(&env.dbang(&["run", "hello@linux-china"]));
+    let run_call = env.deno_calls().into_iter().find(|call| call.starts_with("run")).unwrap();
+    assert!(run_call.contains("--allow-read"), "{}", run_call);
+
+    let output = env.dbang(&["catalog", "show", "linux-china", "hello"]);
+    assert!(!stdout(&output).contains("allow-read"), "{}", stdout(&output));
+    let output = env.dbang(&["catalog", "show", "linux-china", "hello", "--effective"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("allow-read"), "{}", stdout(&output));
+}
+
+#[test]
+fn test_catalog_includes() {
+    let env = TestEnv::new();
+    let catalog = r#"{
+  "includes": [{"catalog": "my-org/tools", "prefix": "tools-"}, "./ops/dbang-catalog.json"],
+  "scripts": {
+    "hello": {"script-ref": "hello.ts"}
+  },
+  "included-scripts": {"hello": "evil/repo"}
+}"#;
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", catalog);
+    env.server.add("/raw/linux-china/dbang-catalog/HEAD/ops/dbang-catalog.json", r#"{"scripts": {"deploy": {"script-ref": "deploy.ts"}}}"#);
+    env.server.add("/raw/my-org/tools/HEAD/dbang-catalog.json", r#"{
+  "defaults": {"permissions": ["allow-net"]},
+  "scripts": {"fmt": {"script-ref": "fmt.ts"}}
+}"#);
+    // trusted catalog still prompts for included catalogs without rules
+    assert_success(&env.dbang(&["trust", "add", "linux-china"]));
+    let output = env.dbang_with_input(&["catalog", "add", "linux-china"], "y\n");
+    assert_success(&output);
+    assert!(stdout(&output).contains("Catalogs included by linux-china/dbang-catalog: my-org/tools"), "{}", stdout(&output));
+
+    let output = env.dbang(&["catalog", "show", "linux-china", "--resolved"]);
+    assert_success(&output);
+    let text = stdout(&output);
+    assert!(text.contains("tools-fmt"), "{}", text);
+    assert!(text.contains("my-org/tools "), "{}", text);
+    assert!(text.contains("linux-china/dbang-catalog/ops/dbang-catalog.json  ops/deploy.ts"), "{}", text);
+    // sources of included scripts in fetched JSON are ignored
+    assert!(!text.contains("evil/repo"), "{}", text);
+
+    assert_success(&env.dbang(&["run", "tools-fmt@linux-china"]));
+    let run_call = env.deno_calls().into_iter().find(|call| call.starts_with("run")).unwrap();
+    assert!(run_call.contains("/raw/my-org/tools/HEAD/fmt.ts"), "{}", run_call);
+    assert!(run_call.contains("--allow-net"), "{}", run_call);
+
+    // included catalogs are checked by deny rules
+    assert_success(&env.dbang(&["trust", "add", "my-org/*", "--level", "never"]));
+    let output = env.dbang(&["catalog", "update", "linux-china"]);
+    assert_eq!(output.status.code(), Some(77));
+}
@@ -0,0 +1,209 @@
+//! Test harness: local HTTP server standing in for GitHub and Deno releases, temp DBANG_HOME and stub deno
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+
+pub const DENO_VERSION: &str = "1.46.3";
+pub const HELLO_SCRIPT: &str = "console.log('Hello');";
+pub const HEAD_COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+/// stub deno, records argv in $DENO_STUB_LOG with one line per call
+const STUB_DENO: &str = r#"#!/bin/sh
+echo "$*" >> "$DENO_STUB_LOG"
+if [ "$1" = "--version" ]; then
+  echo "deno 1.46.3 (release, x86_64-unknown-linux-gnu)"
+fi
+# cache file of module URL in DENO_DIR: remote/<scheme>/<host>_PORT<port>/<sha256 of path>
+module_file() {
+  for arg in "$@"; do url="$arg"; done
+  case "$url" in http://*|https://*) ;; *) return ;; esac
+  scheme="${url%%://*}"; rest="${url#*://}"; host="${rest%%/*}"; path="/${rest#*/}"
+  hash=$(printf '%s' "$path" | sha256sum | cut -d' ' -f1)
+  echo "$DENO_DIR/remote/$scheme/$(echo "$host" | sed 's/:/_PORT/')/$hash"
+}
+# write lockfile for `--lock=<file> --lock-write`, and a module to DENO_DIR
+if [ "$1" = "cache" ]; then
+  case "$*" in
+    *broken.ts*) echo "error: Module not found" >&2; exit 1 ;;
+  esac
+  if [ -n "$DENO_DIR" ]; then
+    mkdir -p "$DENO_DIR/remote" && echo "$*" > "$DENO_DIR/remote/module.ts"
+    file=$(module_file "$@")
+    [ -n "$file" ] && mkdir -p "$(dirname "$file")" && echo "$*" > "$file"
+  fi
+  for arg in "$@"; do
+    case "$arg" in
+      --lock=*) echo '{"version": "3", "remote": {}}' > "${arg#--lock=}" ;;
+    esac
+  done
+fi
+# `--cached-only` fails if the script is not in DENO_DIR
+if [ "$1" = "run" ] && [ -n "$DENO_DIR" ]; then
+  case "$*" in
+    *--cached-only*)
+      file=$(module_file "$@")
+      if [ -n "$file" ] && [ ! -f "$file" ]; then
+        echo "error: Specifier not found in cache, --cached-only is specified." >&2; exit 1
+      fi ;;
+  esac
+fi
+if [ "$1" = "info" ]; then
+  echo "{\"modules\": [{\"specifier\": \"$3\", \"local\": \"$DENO_STUB_CACHED_SCRIPT\"}]}"
+fi
+exit 0
+"#;
+
+pub const HELLO_CATALOG: &str = r#"{
+  "scripts": {
+    "hello": {
+      "script-ref": "hello.ts",
+      "description": "Hello world",
+      "permissions": ["allow-env"]
+    }
+  }
+}"#;
+
+/// HTTP server with fixture files, and paths of requests are recorded
+pub struct FixtureServer {
+    pub base_url: String,
+    routes: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl FixtureServer {
+    pub fn start() -> FixtureServer {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let routes: Arc<Mutex<HashMap<String, Vec<u8>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (server_routes, server_requests) = (routes.clone(), requests.clone());
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let path = request.url().split('?').next().unwrap_or_default().to_string();
+                server_requests.lock().unwrap().push(path.clone());
+                let body = server_routes.lock().unwrap().get(&path).cloned();
+                let response = match body {
+                    Some(body) => tiny_http::Response::from_data(body),
+                    None => tiny_http::Response::from_string("Not Found").with_status_code(404),
+                };
+                let _ = request.respond(response);
+            }
+        });
+        FixtureServer { base_url, routes, requests }
+    }
+
+    pub fn add(&self, path: &str, body: impl Into<Vec<u8>>) {
+        self.routes.lock().unwrap().insert(path.to_string(), body.into());
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// zip of stub deno, same layout as Deno releases
+fn deno_zip() -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default().unix_permissions(0o755);
+    zip.start_file("deno", options).unwrap();
+    zip.write_all(STUB_DENO.as_bytes()).unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+/// isolated DBang environment: DBANG_HOME and HOME in a temp dir, GitHub and Deno releases from fixture server
+pub struct TestEnv {
+    pub dir: tempfile::TempDir,
+    pub server: FixtureServer,
+}
+
+impl TestEnv {
+    pub fn new() -> TestEnv {
+        let dir = tempfile::tempdir().unwrap();
+        let server = FixtureServer::start();
+        server.add("/raw/linux-china/dbang-catalog/HEAD/dbang-catalog.json", HELLO_CATALOG);
+        server.add("/raw/linux-china/dbang-catalog/HEAD/hello.ts", HELLO_SCRIPT);
+        server.add("/api/repos/linux-china/dbang-catalog/commits/HEAD", HEAD_COMMIT);
+        for zip_name in ["deno-x86_64-unknown-linux-gnu.zip", "deno-x86_64-apple-darwin.zip", "deno-aarch64-apple-darwin.zip"] {
+            server.add(&format!("/deno/v{}/{}", DENO_VERSION, zip_name), deno_zip());
+        }
+        // stub deno on PATH for catalogs without Deno version
+        let stub_bin_dir = dir.path().join("stub-bin");
+        std::fs::create_dir_all(&stub_bin_dir).unwrap();
+        write_executable(&stub_bin_dir.join("deno"), STUB_DENO);
+        std::fs::create_dir_all(dir.path().join("home")).unwrap();
+        // script in Deno cache, reported by `deno info --json` of stub deno
+        std::fs::write(dir.path().join("cached-hello.ts"), HELLO_SCRIPT).unwrap();
+        TestEnv { dir, server }
+    }
+
+    pub fn dbang_home(&self) -> PathBuf {
+        self.dir.path().join("dbang")
+    }
+
+    pub fn catalog_file(&self, repo_full_name: &str) -> PathBuf {
+        self.dbang_home().join("catalogs/github").join(repo_full_name).join("dbang-catalog.json")
+    }
+
+    /// command with environment of the test, such as `dbang` or an app installed by dbang
+    pub fn command(&self, program: impl AsRef<Path>) -> Command {
+        let path = format!("{}:{}", self.dir.path().join("stub-bin").to_string_lossy(), std::env::var("PATH").unwrap_or_default());
+        let mut command = Command::new(program.as_ref());
+        command.env("DBANG_HOME", self.dbang_home())
+            .env("HOME", self.dir.path().join("home"))
+            .env("PATH", path)
+            .env("CI", "1")
+            .env("DBANG_GITHUB_RAW_URL", format!("{}/raw", self.server.base_url))
+            .env("DBANG_GITHUB_API_URL", format!("{}/api", self.server.base_url))
+            .env("DBANG_DENO_DOWNLOAD_URL", format!("{}/deno", self.server.base_url))
+            .env("DENO_STUB_LOG", self.dir.path().join("deno-argv.log"))
+            .env("DENO_STUB_CACHED_SCRIPT", self.dir.path().join("cached-hello.ts"))
+            .env_remove("DBANG_OFFLINE")
+            .env_remove("DENO_DIR")
+            .env_remove("DENO_AUTH_TOKENS")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        command
+    }
+
+    pub fn dbang(&self, args: &[&str]) -> Output {
+        self.dbang_with_input(args, "")
+    }
+
+    /// run dbang with answers for prompts
+    pub fn dbang_with_input(&self, args: &[&str], input: &str) -> Output {
+        let mut child = self.command(env!("CARGO_BIN_EXE_dbang")).args(args).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// argv of stub deno calls
+    pub fn deno_calls(&self) -> Vec<String> {
+        std::fs::read_to_string(self.dir.path().join("deno-argv.log"))
+            .map(|text| text.lines().map(|line| line.to_string()).collect())
+            .unwrap_or_default()
+    }
+}
+
+fn write_executable(path: &Path, content: &str) {
+    std::fs::write(path, content).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+/// assert command succeeded, and print output if not
+pub fn assert_success(output: &Output) {
+    assert!(output.status.success(), "status: {}\nstdout: {}\nstderr: {}", output.status,
+            String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
@@ -0,0 +1,23 @@
+//! Tests of dbang as a library, with the same fixture server as CLI tests
+#![cfg(unix)]
+
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn test_non_interactive_runner() {
+    let env = TestEnv::new();
+    // only this test in the process, and the library reads DBang home and GitHub URLs from env
+    std::env::set_var("DBANG_HOME", env.dbang_home());
+    std::env::set_var("DBANG_GITHUB_RAW_URL", format!("{}/raw", env.server.base_url));
+    std::env::set_var("DBANG_GITHUB_API_URL", format!("{}/api", env.server.base_url));
+
+    // catalog without trust rule requires confirm, and it's refused without prompt
+    let error = dbang::Runner::new().non_interactive(true).run("hello@linux-china", &[]).unwrap_err();
+    assert!(matches!(error.downcast_ref::<dbang::DbangError>(), Some(dbang::DbangError::PermissionDenied(_))), "{}", error);
+    assert!(error.to_string().contains("non-interactive"), "{}", error);
+    assert!(!env.catalog_file("linux-china/dbang-catalog").exists());
+    let audit_log = std::fs::read_to_string(env.dbang_home().join("audit.jsonl")).unwrap();
+    assert!(audit_log.contains("rejected in non-interactive mode"), "{}", audit_log);
+}